@@ -0,0 +1,117 @@
+//! An example plugin, making a gradient between two colors picked from the seed.
+//!
+//! Build it with `cargo build --example gradient_plugin`, then copy the library from `target/debug/examples` into the
+//! plugins directory and run it with `hart plugin gradient`.
+
+use {
+  hart::gens::plugin::abi,
+  std::{ffi::CStr, os::raw::c_char, ptr},
+};
+
+/// A nul-terminated string, ready to hand over to hart.
+macro_rules! c_str {
+  ($s:expr) => { concat!($s, "\0").as_ptr() as *const c_char };
+}
+
+static PARAMS: [abi::Param; 2] = [
+  abi::Param {
+    name: c_str!("size"),
+    help: c_str!("Output size in pixels"),
+    kind: abi::KIND_SIZE,
+    short: b'D',
+    required: 0,
+    default: c_str!("256x256"),
+    min: f64::NAN,
+    max: f64::NAN,
+    choices: ptr::null(),
+    choice_count: 0,
+  },
+  abi::Param {
+    name: c_str!("vertical"),
+    help: c_str!("Run the gradient from top to bottom, instead of left to right"),
+    kind: abi::KIND_FLAG,
+    short: b'U',
+    required: 0,
+    default: ptr::null(),
+    min: f64::NAN,
+    max: f64::NAN,
+    choices: ptr::null(),
+    choice_count: 0,
+  },
+];
+
+static PLUGIN: abi::Plugin = abi::Plugin {
+  abi_version: abi::VERSION,
+  name: c_str!("gradient"),
+  about: c_str!("A gradient between two colors picked by the seed"),
+  params: &PARAMS as *const [abi::Param; 2] as *const abi::Param,
+  param_count: 2,
+  run,
+  free_image,
+};
+
+#[no_mangle]
+pub extern "C" fn hart_plugin() -> *const abi::Plugin {
+  &PLUGIN
+}
+
+/// Copy an error message into hart's buffer, cutting it short if it has to. A buffer with no room at all gets
+/// nothing, but the run still fails.
+unsafe fn fail(message: &str, error: *mut c_char, error_len: usize) -> i32 {
+  if error_len == 0 {
+    return 1;
+  }
+  let len = message.len().min(error_len.saturating_sub(1));
+  ptr::copy_nonoverlapping(message.as_ptr() as *const c_char, error, len);
+  *error.add(len) = 0;
+  1
+}
+
+unsafe extern "C" fn run(
+  args: *const abi::Arg, arg_count: usize, seed: *const u8, seed_len: usize, out: *mut abi::Image,
+  error: *mut c_char, error_len: usize,
+) -> i32 {
+  let mut size = None;
+  let mut vertical = false;
+  for arg in std::slice::from_raw_parts(args, arg_count) {
+    let value = CStr::from_ptr(arg.value).to_string_lossy();
+    match CStr::from_ptr(arg.name).to_bytes() {
+      b"size" => size = value.split_once('x').and_then(|(w, h)| Some((w.parse::<u32>().ok()?, h.parse::<u32>().ok()?))),
+      b"vertical" => vertical = value == "true",
+      _ => (),
+    }
+  }
+  let (width, height) = match size {
+    Some(size) => size,
+    None => return fail("no size was given", error, error_len),
+  };
+
+  // FNV-1a, to turn the seed into a pair of colors
+  let hash = std::slice::from_raw_parts(seed, seed_len).iter()
+    .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100_0000_01b3));
+  let color = |shift: u32| [(hash >> shift) as u8, (hash >> (shift + 8)) as u8, (hash >> (shift + 16)) as u8];
+  let (from, to) = (color(0), color(24));
+
+  let mut data = Vec::with_capacity(width as usize * height as usize * 3);
+  for y in 0..height {
+    for x in 0..width {
+      let (pos, len) = if vertical { (y, height) } else { (x, width) };
+      let amt = pos as f32 / (len - 1).max(1) as f32;
+      for c in 0..3 {
+        data.push((from[c] as f32 * (1.0 - amt) + to[c] as f32 * amt) as u8);
+      }
+    }
+  }
+  let data = Box::into_raw(data.into_boxed_slice()) as *mut u8;
+  *out = abi::Image { width, height, channels: 3, data };
+  0
+}
+
+unsafe extern "C" fn free_image(image: *mut abi::Image) {
+  let image = &mut *image;
+  if !image.data.is_null() {
+    let len = image.width as usize * image.height as usize * image.channels as usize;
+    drop(Box::from_raw(ptr::slice_from_raw_parts_mut(image.data, len)));
+    image.data = ptr::null_mut();
+  }
+}
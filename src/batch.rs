@@ -0,0 +1,204 @@
+//! Rendering many variants of a piece in one go: several seeds, sweeps over parameters, or both.
+//!
+//! A batch is every combination of its seeds and each sweep's values. Each variant gets its own output path, filled
+//! in from a template like `out-{seed}-{octaves}.png`, so they don't all land in the same file.
+
+use {
+  crate::{params::Params, seed},
+  std::{collections::HashSet, ops::RangeInclusive},
+};
+
+/// One seed in a batch, along with how to write it in output names.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Seed {
+  /// What `{seed}` is replaced with in output templates
+  pub label: String,
+  pub bytes: Vec<u8>,
+}
+
+impl Seed {
+  /// A seed given as text, labelled with that text.
+  pub fn text(text: &str) -> Seed {
+    Seed { label: text.to_string(), bytes: text.as_bytes().to_vec() }
+  }
+
+  /// A seed given as a nonnegative integer, the same way as `--seed-int`.
+  pub fn int(n: u64) -> Seed {
+    Seed { label: n.to_string(), bytes: n.to_be_bytes().to_vec() }
+  }
+
+  /// Raw seed bytes, labelled with their hex.
+  pub fn bytes(bytes: Vec<u8>) -> Seed {
+    Seed { label: seed::to_hex(&bytes), bytes }
+  }
+}
+
+/// One variant in a batch: a seed, and the parameters to render it with.
+pub struct Variant {
+  pub seed: Seed,
+  /// The values of the parameters being swept, if any
+  pub swept: Vec<(String, String)>,
+  pub params: Params,
+}
+
+/// Parse a range of integers, written like Rust's: `0..10` leaves out 10, `0..=10` includes it.
+fn parse_range(s: &str) -> Option<RangeInclusive<i64>> {
+  let (start, end) = s.split_once("..")?;
+  let start = start.trim().parse().ok()?;
+  let range = match end.strip_prefix('=') {
+    Some(end) => start..=end.trim().parse().ok()?,
+    None => start..=end.trim().parse::<i64>().ok()?.checked_sub(1)?,
+  };
+  Some(range)
+}
+
+/// Parse the seeds for `--seed-range`, like `0..100`.
+pub fn seed_range(s: &str) -> Result<Vec<Seed>, String> {
+  let range = parse_range(s).ok_or_else(|| format!("{} is not a range like 0..100 or 1..=6", s))?;
+  if *range.start() < 0 {
+    return Err(format!("{} has negative seeds in it", s));
+  }
+  if range.is_empty() {
+    return Err(format!("{} is empty", s));
+  }
+  Ok(range.map(|n| Seed::int(n as u64)).collect())
+}
+
+/// One parameter, and all the values to try it with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sweep {
+  pub name: String,
+  pub values: Vec<String>,
+}
+
+impl Sweep {
+  /// Parse a sweep written like `name=1..6` (a range of integers, the same way as `seed_range`) or `name=a,b,c`.
+  /// The values aren't checked against the parameter, since that happens when the variants are resolved.
+  pub fn parse(s: &str) -> Result<Sweep, String> {
+    let (name, values) = s.split_once('=').ok_or_else(|| format!("{} is not a sweep like octaves=1..6", s))?;
+    let name = name.trim();
+    if name.is_empty() {
+      return Err(format!("{} doesn't say which parameter to sweep", s));
+    }
+    let values: Vec<String> = match parse_range(values) {
+      Some(range) => range.map(|n| n.to_string()).collect(),
+      None => values.split(',').map(|v| v.trim().to_string()).collect(),
+    };
+    if values.is_empty() || values.iter().any(String::is_empty) {
+      return Err(format!("{} has an empty value in it", s));
+    }
+    Ok(Sweep { name: name.to_string(), values })
+  }
+}
+
+/// Every combination of the sweeps' values, as `(name, value)` pairs in the order the sweeps were given. The last
+/// sweep varies fastest. With no sweeps, there's just the one combination of nothing.
+pub fn combinations(sweeps: &[Sweep]) -> Vec<Vec<(String, String)>> {
+  let mut combinations = vec![vec![]];
+  for sweep in sweeps {
+    combinations = combinations.into_iter()
+      .flat_map(|combination: Vec<(String, String)>| sweep.values.iter().map(move |value| {
+        let mut combination = combination.clone();
+        combination.push((sweep.name.clone(), value.clone()));
+        combination
+      }))
+      .collect();
+  }
+  combinations
+}
+
+/// Fill in a template like `out-{seed}-{octaves}.png` with the named values. `{{` and `}}` are literal braces.
+pub fn fill<N, V>(template: &str, values: &[(N, V)]) -> Result<String, String>
+where
+  N: AsRef<str>,
+  V: AsRef<str>,
+{
+  let mut filled = String::with_capacity(template.len());
+  let mut rest = template;
+  while let Some(at) = rest.find(['{', '}']) {
+    filled.push_str(&rest[..at]);
+    let brace = &rest[at..];
+    if brace.starts_with("{{") || brace.starts_with("}}") {
+      filled.push_str(&brace[..1]);
+      rest = &brace[2..];
+    } else if brace.starts_with('}') {
+      return Err(format!("{} has a }} without a {{", template));
+    } else {
+      let end = brace.find('}').ok_or_else(|| format!("{} has a {{ without a }}", template))?;
+      let name = &brace[1..end];
+      let (_, value) = values.iter()
+        .find(|(n, _)| n.as_ref() == name)
+        .ok_or_else(|| format!("{} has {{{}}} in it, but there's nothing by that name", template, name))?;
+      filled.push_str(value.as_ref());
+      rest = &brace[end + 1..];
+    }
+  }
+  filled.push_str(rest);
+  Ok(filled)
+}
+
+/// Make sure every variant is going somewhere different, so none overwrite each other.
+pub fn check_distinct(paths: &[String]) -> Result<(), String> {
+  let mut seen = HashSet::new();
+  match paths.iter().find(|path| !seen.insert(path.as_str())) {
+    Some(path) => Err(format!(
+      "more than one variant would be written to {}; add {{seed}} or the swept parameters to the output name",
+      path,
+    )),
+    None => Ok(()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn ranges_are_written_like_rust() {
+    let labels = |s| seed_range(s).unwrap().into_iter().map(|s| s.label).collect::<Vec<_>>();
+    assert_eq!(labels("0..3"), ["0", "1", "2"]);
+    assert_eq!(labels("5..=7"), ["5", "6", "7"]);
+    assert_eq!(seed_range("7").unwrap_err(), "7 is not a range like 0..100 or 1..=6");
+    assert!(seed_range("3..3").is_err());
+    assert!(seed_range("-2..2").is_err());
+    assert_eq!(seed_range("4..5").unwrap()[0].bytes, seed::from_int("4").unwrap());
+  }
+
+  #[test]
+  fn sweeps_take_ranges_or_lists() {
+    assert_eq!(Sweep::parse("octaves=1..=3").unwrap().values, ["1", "2", "3"]);
+    assert_eq!(Sweep::parse("algorithm=perlin, worley").unwrap().values, ["perlin", "worley"]);
+    assert_eq!(Sweep::parse("from=a=b.png").unwrap(), Sweep { name: "from".into(), values: vec!["a=b.png".into()] });
+    assert!(Sweep::parse("octaves").is_err());
+    assert!(Sweep::parse("=1..3").is_err());
+    assert!(Sweep::parse("scale=1%,,2%").is_err());
+  }
+
+  #[test]
+  fn combinations_cover_everything() {
+    let sweeps = [Sweep::parse("x=1..=2").unwrap(), Sweep::parse("y=p,q,r").unwrap()];
+    let all = combinations(&sweeps);
+    assert_eq!(all.len(), 6);
+    assert_eq!(all[0], [("x".to_string(), "1".to_string()), ("y".to_string(), "p".to_string())]);
+    assert_eq!(all[1][1].1, "q");
+    assert_eq!(all[5][0].1, "2");
+    assert_eq!(combinations(&[]), [vec![]]);
+  }
+
+  #[test]
+  fn templates_fill_in_names() {
+    let values = [("seed", "12"), ("octaves", "3")];
+    assert_eq!(fill("out-{seed}-{octaves}.png", &values).unwrap(), "out-12-3.png");
+    assert_eq!(fill("plain.png", &values).unwrap(), "plain.png");
+    assert_eq!(fill("{{seed}}-{seed}", &values).unwrap(), "{seed}-12");
+    assert!(fill("out-{scale}.png", &values).is_err());
+    assert!(fill("out-{seed.png", &values).is_err());
+    assert!(fill("out-}.png", &values).is_err());
+  }
+
+  #[test]
+  fn clashing_paths_are_caught() {
+    assert!(check_distinct(&["a.png".to_string(), "b.png".to_string()]).is_ok());
+    assert!(check_distinct(&["a.png".to_string(), "b.png".to_string(), "a.png".to_string()]).is_err());
+  }
+}
@@ -0,0 +1,359 @@
+//! Defaults for hart's options and generators' parameters, from config files and the environment, so they don't have
+//! to be given on every run.
+//!
+//! Config files are TOML. Top-level keys set options, and a table for a generator sets its parameters:
+//!
+//! ```toml
+//! format = "png"
+//! png-compression = "best"
+//!
+//! [basic.worley]
+//! size = [3840, 2160]
+//! octaves = 3
+//! ```
+//!
+//! `format` only applies when the output's extension doesn't say what format to write, and relative paths in
+//! parameters are relative to the config file.
+//!
+//! The environment can set the same things: `HART_FORMAT` for `format`, and `HART_BASIC_WORLEY_SIZE` for `size` in
+//! `[basic.worley]`, with letters uppercased and dashes turned into underscores.
+//!
+//! From lowest to highest precedence, values come from hart's built-in defaults, the user's config file, the nearest
+//! `hart.toml` in the current directory or one above it, the environment, and finally the command line.
+
+use {
+  crate::{
+    gens::{Category, Gen, Registry, STDIO},
+    jobs,
+    output::{self, Depth, Format},
+    params::Kind,
+  },
+  std::{
+    env,
+    fmt,
+    fs,
+    path::{Path, PathBuf},
+  },
+  toml::{value::Table, Value},
+};
+
+/// The options a config can set, which are named after their command line options.
+pub const OPTIONS: &[&str] = &["format", "depth", "quality", "png-compression", "png-filter", "quiet", "verbose"];
+
+/// The name of project-local config files.
+pub const LOCAL_NAME: &str = "hart.toml";
+
+/// Where a value came from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+  /// A config file
+  File(PathBuf),
+  /// An environment variable, by name
+  Env(String),
+}
+
+impl fmt::Display for Source {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Source::File(path) => write!(f, "{}", path.display()),
+      Source::Env(var) => write!(f, "${}", var),
+    }
+  }
+}
+
+/// A value from a config, in text form, and where it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setting {
+  pub value: String,
+  pub source: Source,
+}
+
+/// The values from one config file, or the environment.
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Layer {
+  options: Vec<(String, Setting)>,
+  /// Parameters, by the generator's full name, like `basic/worley`
+  params: Vec<(String, String, Setting)>,
+}
+
+/// Defaults for options and parameters, gathered from every config file and the environment.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Config {
+  /// Lowest precedence first
+  layers: Vec<Layer>,
+  /// Generators which were configured but don't exist, like ones from plugins which aren't installed
+  pub unknown: Vec<(String, Source)>,
+}
+
+/// Make sure an option's value makes sense.
+fn check_option(name: &str, value: &str) -> Result<(), String> {
+  let ok = match name {
+    "format" => Format::by_name(value).is_some(),
+    "depth" => Depth::by_name(value).is_some(),
+    "quality" => value.parse::<u8>().is_ok_and(|q| (1..=100).contains(&q)),
+    "png-compression" => output::png_compression(value).is_some(),
+    "png-filter" => output::png_filter(value).is_some(),
+    "quiet" => value == "true" || value == "false",
+    "verbose" => value.parse::<u8>().is_ok_and(|v| v <= 2),
+    _ => return Err(format!("there's no option called {}", name)),
+  };
+  if ok {
+    Ok(())
+  } else {
+    Err(format!("{} isn't a valid {}", value, name))
+  }
+}
+
+/// The environment variable for an option, or a generator's parameter.
+pub fn env_var(parts: &[&str]) -> String {
+  let name: Vec<_> = parts.iter().map(|part| part.to_uppercase().replace('-', "_")).collect();
+  format!("HART_{}", name.join("_"))
+}
+
+impl Config {
+  /// A config with nothing set, so everything is the built-in default.
+  pub fn new() -> Config {
+    Config::default()
+  }
+
+  /// Add a config file's contents on top of everything added so far.
+  pub fn add_file(&mut self, contents: &str, path: &Path, registry: &Registry) -> Result<(), String> {
+    let source = || Source::File(path.to_path_buf());
+    let table: Table = toml::from_str(contents).map_err(|e| e.to_string())?;
+    let mut layer = Layer::default();
+    for (key, value) in &table {
+      // plugin generators can be configured even when the plugin isn't there, so this checks every category
+      let category = match (value, Category::by_name(key)) {
+        (Value::Table(gens), Some(_)) => gens,
+        (_, Some(_)) => return Err(format!("{} should be a table of generators", key)),
+        (value, None) => {
+          let text = jobs::text(value).map_err(|e| format!("{}: {}", key, e))?;
+          check_option(key, &text)?;
+          layer.options.push((key.clone(), Setting { value: text, source: source() }));
+          continue;
+        }
+      };
+      for (command, params) in category {
+        let generator = format!("{}/{}", key, command);
+        let gen = match registry.find(key, command) {
+          Some(gen) => gen,
+          None => {
+            self.unknown.push((generator, source()));
+            continue;
+          }
+        };
+        let params = params.as_table().ok_or_else(|| format!("{} should be a table of parameters", generator))?;
+        let schema = gen.params();
+        for (name, value) in params {
+          let param = schema.iter()
+            .find(|param| param.name == name)
+            .ok_or_else(|| format!("{} doesn't have a parameter called {}", generator, name))?;
+          let mut text = jobs::text(value).and_then(|text| param.parse(&text).map(|_| text))
+            .map_err(|e| format!("{}: {}: {}", generator, name, e))?;
+          // like in job files, paths are relative to the file they're written in
+          if param.kind == Kind::Path && text != STDIO {
+            text = path.parent().unwrap_or_else(|| Path::new("")).join(text).display().to_string();
+          }
+          // aliases are saved under the generator's real name
+          let generator = format!("{}/{}", key, gen.command());
+          layer.params.push((generator, name.clone(), Setting { value: text, source: source() }));
+        }
+      }
+    }
+    self.layers.push(layer);
+    Ok(())
+  }
+
+  /// Add the environment variables on top of everything added so far, reading them with `var`.
+  pub fn add_env(&mut self, registry: &Registry, var: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+    let mut layer = Layer::default();
+    for option in OPTIONS {
+      let name = env_var(&[option]);
+      if let Some(value) = var(&name) {
+        check_option(option, &value).map_err(|e| format!("${}: {}", name, e))?;
+        layer.options.push((option.to_string(), Setting { value, source: Source::Env(name) }));
+      }
+    }
+    for gen in registry.all() {
+      for param in gen.params() {
+        let name = env_var(&[gen.category().name(), gen.command(), param.name]);
+        if let Some(value) = var(&name) {
+          param.parse(&value).map_err(|e| format!("${}: {}", name, e))?;
+          let generator = format!("{}/{}", gen.category().name(), gen.command());
+          layer.params.push((generator, param.name.to_string(), Setting { value, source: Source::Env(name) }));
+        }
+      }
+    }
+    self.layers.push(layer);
+    Ok(())
+  }
+
+  /// Load the user's config file, the project's, and the environment, whichever of them there are.
+  pub fn load(registry: &Registry) -> Result<Config, String> {
+    let mut config = Config::new();
+    for path in user_file().into_iter().chain(local_file()) {
+      match fs::read_to_string(&path) {
+        Ok(contents) => config.add_file(&contents, &path, registry).map_err(|e| format!("{}: {}", path.display(), e))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(format!("couldn't read {}: {}", path.display(), e)),
+      }
+    }
+    config.add_env(registry, |name| env::var(name).ok())?;
+    Ok(config)
+  }
+
+  /// An option's value, if any config sets it.
+  pub fn option(&self, name: &str) -> Option<&Setting> {
+    self.layers.iter().rev()
+      .flat_map(|layer| layer.options.iter())
+      .find(|(option, _)| option == name)
+      .map(|(_, setting)| setting)
+  }
+
+  /// Every parameter set for a generator, by its full name like `basic/worley`, with the highest precedence value for
+  /// each.
+  pub fn params(&self, generator: &str) -> Vec<(&str, &Setting)> {
+    let mut params: Vec<(&str, &Setting)> = vec![];
+    for (gen, name, setting) in self.layers.iter().flat_map(|layer| layer.params.iter()) {
+      if gen == generator {
+        params.retain(|(other, _)| other != name);
+        params.push((name, setting));
+      }
+    }
+    params
+  }
+
+  /// Every generator with parameters set, by full name, in the order they were first set.
+  pub fn generators(&self) -> Vec<&str> {
+    let mut gens: Vec<&str> = vec![];
+    for (gen, _, _) in self.layers.iter().flat_map(|layer| layer.params.iter()) {
+      if !gens.contains(&gen.as_str()) {
+        gens.push(gen);
+      }
+    }
+    gens
+  }
+
+  /// The value every option has, and of every parameter of each of `gens`, along with where each came from, a line
+  /// each, as `hart config show` prints them.
+  pub fn show(&self, gens: &[&dyn Gen]) -> Vec<String> {
+    let mut lines = vec![];
+    let line = |name: &str, value: &str, source: String| format!("{} = {}  ({})", name, value, source);
+    for name in OPTIONS {
+      lines.push(match self.option(name) {
+        Some(setting) => line(name, &setting.value, setting.source.to_string()),
+        None => line(name, builtin_option(name), "built-in".to_string()),
+      });
+    }
+    for gen in gens {
+      let generator = format!("{}/{}", gen.category().name(), gen.command());
+      let configured = self.params(&generator);
+      lines.push(String::new());
+      lines.push(format!("[{}]", generator));
+      for param in gen.params() {
+        lines.push(match configured.iter().find(|(name, _)| *name == param.name) {
+          Some((_, setting)) => line(param.name, &setting.value, setting.source.to_string()),
+          None => match (param.default, param.kind) {
+            (Some(default), _) => line(param.name, default, "built-in".to_string()),
+            (None, Kind::Flag) => line(param.name, "false", "built-in".to_string()),
+            (None, _) if param.required => line(param.name, "unset", "required".to_string()),
+            (None, _) => line(param.name, "unset", "built-in".to_string()),
+          },
+        });
+      }
+    }
+    lines
+  }
+}
+
+/// The built-in default for an option, in words.
+fn builtin_option(name: &str) -> &'static str {
+  match name {
+    "format" => "from the output's extension, or png",
+    "depth" => "from the format",
+    "quality" => "90",
+    "png-compression" => output::PNG_COMPRESSIONS[0],
+    "png-filter" => output::PNG_FILTERS[0],
+    "quiet" => "false",
+    _ => "0",
+  }
+}
+
+/// The user's config file: `$XDG_CONFIG_HOME/hart/config.toml`, or `~/.config/hart/config.toml`.
+pub fn user_file() -> Option<PathBuf> {
+  let config = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+    .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+  Some(config.join("hart").join("config.toml"))
+}
+
+/// The project's config file: the nearest `hart.toml` in the current directory or any above it.
+pub fn local_file() -> Option<PathBuf> {
+  let cwd = env::current_dir().ok()?;
+  cwd.ancestors().map(|dir| dir.join(LOCAL_NAME)).find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn file(config: &mut Config, contents: &str, name: &str) -> Result<(), String> {
+    config.add_file(contents, Path::new(name), &Registry::builtin())
+  }
+
+  #[test]
+  fn later_layers_win() {
+    let mut config = Config::new();
+    file(&mut config, "format = 'tiff'\nquality = 80\n[basic.worley]\nsize = [30, 20]\noctaves = 2", "user").unwrap();
+    file(&mut config, "quality = 95\n[basic.cellular]\noctaves = 4", "local").unwrap();
+    file(&mut config, "[project.mottle]\nfrom = 'a.png'\nto = '-'", "proj/hart.toml").unwrap();
+    let env = |name: &str| match name {
+      "HART_PNG_FILTER" => Some("paeth".to_string()),
+      "HART_BASIC_WORLEY_SIZE" => Some("64x48".to_string()),
+      _ => None,
+    };
+    config.add_env(&Registry::builtin(), env).unwrap();
+
+    let option = |name| config.option(name).map(|s| (s.value.as_str(), s.source.to_string()));
+    assert_eq!(option("format"), Some(("tiff", "user".to_string())));
+    assert_eq!(option("quality"), Some(("95", "local".to_string())));
+    assert_eq!(option("png-filter"), Some(("paeth", "$HART_PNG_FILTER".to_string())));
+    assert_eq!(option("depth"), None);
+
+    // the alias is filed under worley's real name
+    let params: Vec<_> = config.params("basic/worley").into_iter().map(|(n, s)| (n, s.value.as_str())).collect();
+    assert_eq!(params, [("octaves", "4"), ("size", "64x48")]);
+    assert_eq!(config.generators(), ["basic/worley", "project/mottle"]);
+    let params: Vec<_> = config.params("project/mottle").into_iter().map(|(n, s)| (n, s.value.as_str())).collect();
+    assert_eq!(params, [("from", "proj/a.png"), ("to", "-")]);
+  }
+
+  #[test]
+  fn bad_values_are_caught() {
+    let mut config = Config::new();
+    assert_eq!(file(&mut config, "fromat = 'png'", "f").unwrap_err(), "there's no option called fromat");
+    assert_eq!(file(&mut config, "quality = 101", "f").unwrap_err(), "101 isn't a valid quality");
+    assert_eq!(
+      file(&mut config, "[basic.worley]\noctave = 2", "f").unwrap_err(),
+      "basic/worley doesn't have a parameter called octave",
+    );
+    let error = file(&mut config, "[basic.worley]\noctaves = 'many'", "f").unwrap_err();
+    assert!(error.starts_with("basic/worley: octaves"));
+    assert_eq!(file(&mut config, "basic = 3", "f").unwrap_err(), "basic should be a table of generators");
+    let env = |name: &str| if name == "HART_DEPTH" { Some("7".to_string()) } else { None };
+    assert_eq!(config.add_env(&Registry::builtin(), env).unwrap_err(), "$HART_DEPTH: 7 isn't a valid depth");
+  }
+
+  #[test]
+  fn missing_generators_are_only_noted() {
+    let mut config = Config::new();
+    file(&mut config, "[plugin.gradient]\nsize = [4, 4]", "f").unwrap();
+    assert_eq!(config.unknown, [("plugin/gradient".to_string(), Source::File(PathBuf::from("f")))]);
+    assert!(config.params("plugin/gradient").is_empty());
+  }
+
+  #[test]
+  fn env_vars_are_shouty() {
+    assert_eq!(env_var(&["png-compression"]), "HART_PNG_COMPRESSION");
+    assert_eq!(env_var(&["basic", "worley", "size"]), "HART_BASIC_WORLEY_SIZE");
+  }
+}
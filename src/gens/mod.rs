@@ -3,9 +3,22 @@
 //! The generators themselves are fairly simple; the (gross) workhorse code will likely live in crate::utils
 
 use {
-  clap::{App, ArgMatches},
-  image::ImageError,
-  std::io::{Error, Write},
+  crate::{
+    output::Output,
+    params::{Param, Params},
+    utils::render::{Raster, Renderer},
+  },
+  image::{DynamicImage, ImageError},
+  std::{
+    collections::HashMap,
+    error,
+    fmt,
+    fs,
+    io::{self, Cursor, Error, Read as _},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::SystemTime,
+  },
 };
 
 mod test_ascii;
@@ -16,13 +29,53 @@ mod worley;
 pub use worley::WorleyGen;
 mod mottler;
 pub use mottler::Mottler;
+mod registry;
+pub use registry::Registry;
+pub mod plugin;
 
-#[allow(dead_code)]
+/// Why a generator (or writing its output) failed.
 #[derive(Debug)]
 pub enum GenFail {
   Io(Error),
   Image(ImageError),
+  /// One of the parameters, or some combination of them, doesn't make sense
   BadArg(String),
+  /// An input could be read, but what's in it doesn't work, like two images that need to be the same size but aren't
+  Data(String),
+  /// A plugin couldn't be loaded, broke the ABI, or failed while running
+  Plugin(String),
+  /// Another failure, with some context for it, like which file it happened with
+  Context(String, Box<GenFail>),
+}
+
+impl GenFail {
+  /// The failure underneath all of the context that's been added to it.
+  pub fn root(&self) -> &GenFail {
+    match self {
+      GenFail::Context(_, inner) => inner.root(),
+      other => other,
+    }
+  }
+}
+
+impl fmt::Display for GenFail {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      GenFail::Io(e) => write!(f, "{}", e),
+      GenFail::Image(e) => write!(f, "{}", e),
+      GenFail::BadArg(msg) | GenFail::Data(msg) | GenFail::Plugin(msg) => write!(f, "{}", msg),
+      GenFail::Context(context, _) => write!(f, "{}", context),
+    }
+  }
+}
+
+impl error::Error for GenFail {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match self {
+      GenFail::Context(_, inner) => Some(&**inner),
+      _ => None,
+    }
+  }
 }
 
 impl From<Error> for GenFail {
@@ -43,6 +96,63 @@ impl From<ImageError> for GenFail {
 
 pub type Result<T> = std::result::Result<T, GenFail>;
 
+/// Attach context to failures, saying what was going on when they happened.
+pub trait Context<T> {
+  fn context<C: fmt::Display>(self, context: impl FnOnce() -> C) -> Result<T>;
+}
+
+impl<T, E: Into<GenFail>> Context<T> for std::result::Result<T, E> {
+  fn context<C: fmt::Display>(self, context: impl FnOnce() -> C) -> Result<T> {
+    self.map_err(|e| GenFail::Context(context().to_string(), Box::new(e.into())))
+  }
+}
+
+/// The path that means stdin for inputs, or stdout for outputs.
+pub const STDIO: &str = "-";
+
+/// An input image, decoded by whoever wants it first. Anyone else wanting the same image waits for that, but nothing
+/// wanting a different one does. It stays empty if decoding fails, so the next try gets the error too.
+type Slot = Arc<Mutex<Option<Arc<DynamicImage>>>>;
+
+/// Input images which have been decoded, or are being decoded, by path and when they were last modified, so running
+/// generators over and over (in a batch, say) only decodes each input once, but still notices when one changes.
+type Decoded = HashMap<PathBuf, (Option<SystemTime>, Slot)>;
+
+static DECODED: OnceLock<Mutex<Decoded>> = OnceLock::new();
+
+/// Open an input image, working out its format from its contents rather than trusting the extension.
+/// The path `-` reads the image from stdin instead. Images are only decoded once, and shared after that.
+pub fn load_image(path: &Path) -> Result<Arc<DynamicImage>> {
+  let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+  // the cache is only locked long enough to find the image's slot, so different images can be decoded at once
+  let slot = {
+    let mut decoded = DECODED.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    match decoded.get(path) {
+      Some((when, slot)) if *when == modified => slot.clone(),
+      _ => {
+        let slot = Slot::default();
+        decoded.insert(path.to_path_buf(), (modified, slot.clone()));
+        slot
+      }
+    }
+  };
+  let mut image = slot.lock().unwrap_or_else(|e| e.into_inner());
+  if let Some(image) = &*image {
+    return Ok(image.clone());
+  }
+  let decoded = if path == Path::new(STDIO) {
+    let mut data = Vec::new();
+    io::stdin().lock().read_to_end(&mut data).context(|| "couldn't read stdin")?;
+    image::io::Reader::new(Cursor::new(data)).with_guessed_format()?.decode().context(|| "couldn't decode stdin")?
+  } else {
+    let reader = image::io::Reader::open(path).context(|| format!("couldn't open {}", path.display()))?;
+    reader.with_guessed_format()?.decode().context(|| format!("couldn't decode {}", path.display()))?
+  };
+  let decoded = Arc::new(decoded);
+  *image = Some(decoded.clone());
+  Ok(decoded)
+}
+
 /// Describes the type of generator the Gen implements.
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub enum Category {
@@ -52,11 +162,17 @@ pub enum Category {
   Basic,
   /// An art piece, built out of other generators etc.
   Project,
+  /// A generator loaded at runtime from a plugin
+  Plugin,
 }
 
 impl Category {
-  pub fn all() -> [Category; 3] {
-    [ Category::Test, Category::Basic, Category::Project ]
+  pub fn all() -> [Category; 4] {
+    [ Category::Test, Category::Basic, Category::Project, Category::Plugin ]
+  }
+
+  pub fn by_name(name: &str) -> Option<Category> {
+    Category::all().iter().cloned().find(|cat| cat.name() == name)
   }
 
   pub fn name(&self) -> &'static str {
@@ -64,6 +180,7 @@ impl Category {
       Category::Test => "test",
       Category::Basic => "basic",
       Category::Project => "project",
+      Category::Plugin => "plugin",
     }
   }
 
@@ -72,11 +189,11 @@ impl Category {
       Category::Test => "A generator just meant to be used to test other infrastructure",
       Category::Basic => "A single, simple generator of noise, maybe with octaves or inversion applied",
       Category::Project => "An art piece, built out of other generators etc.",
+      Category::Plugin => "A generator loaded from the plugins directory",
     }
   }
 }
 
-// TODO: Add categories to generators, use them in main
 /// A trait normalizing the interface across all generators
 pub trait Gen: Sync {
   /// The category that the command is in, e.g. basic noise generation or tests
@@ -85,29 +202,53 @@ pub trait Gen: Sync {
   fn command(&self) -> &'static str;
   /// The human-friendly name of this subcommand
   fn about(&self) -> &'static str;
-  /// Set up the subcommand for this generator, to fill out any needed extra command line options.
-  /// Note you _should not_ add a subcommand for your gen: the parameter is the `SubCommand` which will be added for you.
-  fn setup_cmd<'a, 'b>(&self, app: App<'a, 'b>) -> App<'a, 'b>;
-  /// Actually run the generator. Will be passed the subcommand's arguments only.
-  fn run(&self, opts: &ArgMatches, seed: &[u8], output: &mut dyn Write) -> Result<()>;
+  /// Other names the generator can be run by, within its category
+  fn aliases(&self) -> &'static [&'static str] { &[] }
+  /// Free-form labels to help find generators, like "noise" or "image input"
+  fn tags(&self) -> &'static [&'static str] { &[] }
+  /// Whether to leave the generator out of help and listings. It can still be run by name.
+  fn hidden(&self) -> bool { false }
+  /// Whether the generator is still a work in progress, so its output might change between versions
+  fn experimental(&self) -> bool { false }
+  /// The parameters this generator takes. Frontends build their options from this, and validate against it.
+  fn params(&self) -> Vec<Param>;
+  /// Actually run the generator. The parameters have already been validated against `params`.
+  /// Encoding and writing the output is handled by `crate::output`, so generators should never do it themselves.
+  /// By default this renders the whole of `raster` at once, so generators which have one needn't implement it.
+  fn run(&self, params: &Params, seed: &[u8]) -> Result<Output> {
+    let raster = self.raster(params, seed)?.expect("generators without a raster have to implement run");
+    Ok(raster.render_all(&Renderer::new()).into())
+  }
+  /// The generator's output as a `Raster`, for generators which can render it in pieces. This lets it be streamed
+  /// out without ever being in memory all at once. `None`, the default, means it can only be made all at once by
+  /// `run`.
+  fn raster(&self, _params: &Params, _seed: &[u8]) -> Result<Option<Box<dyn Raster>>> {
+    Ok(None)
+  }
 }
 
-impl dyn Gen {
-  pub fn all() -> [&'static dyn Gen; 4] {
-    [
-      &TestAscii,
-      &Test2D,
-      &WorleyGen,
-      &Mottler,
-    ]
-  }
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    image::{GenericImageView as _, RgbImage},
+    std::{env, process, thread},
+  };
 
-  pub fn by_cli(category: &str, name: &str) -> Option<&'static dyn Gen> {
-    for cand in Gen::all().iter() {
-      if cand.category().name() == category && cand.command() == name {
-        return Some(*cand);
-      }
-    }
-    None
+  #[test]
+  fn decoding_one_image_doesnt_hold_up_others() {
+    let path = env::temp_dir().join(format!("hart-load-image-{}.png", process::id()));
+    RgbImage::new(3, 2).save(&path).unwrap();
+    // pretend another image is partway through being decoded
+    let busy = Slot::default();
+    let busy_path = env::temp_dir().join("hart-busy.png");
+    DECODED.get_or_init(Default::default).lock().unwrap().insert(busy_path, (None, busy.clone()));
+    let _decoding = busy.lock().unwrap();
+
+    let loading = path.clone();
+    let first = thread::spawn(move || load_image(&loading)).join().unwrap().unwrap();
+    assert_eq!(first.dimensions(), (3, 2));
+    assert!(Arc::ptr_eq(&first, &load_image(&path).unwrap()), "the image was decoded twice");
+    fs::remove_file(path).unwrap();
   }
-}
\ No newline at end of file
+}
@@ -1,39 +1,134 @@
 
 use {
-  clap::{App, Arg, ArgMatches},
-  std::{
-    io,
-    time::Instant,
+  crate::{
+    params::{Kind, Param, Params},
+    utils::{
+      noise::{Worley, Noise2D, Pos},
+      render::{Pixels, Raster, Renderer},
+      Span,
+    },
   },
-  crate::utils::noise::{Worley, Noise2D, Pos},
   image::{
-    codecs::png::{PngEncoder, CompressionType, FilterType},
-    ColorType,
     GenericImageView,
+    Luma,
     Pixel,
+    Rgb,
   },
-  rayon::iter::{IntoParallelIterator, ParallelExtend, ParallelIterator},
+  std::f32::consts::{PI, TAU},
 };
 
-fn validate_pct(s: String) -> Result<(), String> {
-  decode_pct(&s).map(|_| ())
+fn lerp(from: u8, to: u8, amt: f32) -> u8 {
+  let from_scaled = from as f32 * (1.0 - amt);
+  let to_scaled = to as f32 * amt;
+  let sum = from_scaled + to_scaled;
+  sum as u8
+}
+
+/// The lowest and highest of some positions.
+fn extremes(positions: impl Iterator<Item = f32>) -> (f32, f32) {
+  positions.fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), p| (lo.min(p), hi.max(p)))
 }
 
-fn decode_pct(mut s: &str) -> Result<f32, String> {
-  if s.ends_with("%") {
-    s = &s[..s.len()-1];
+/// Which way a blend goes: how far along it each pixel is, and how the noise is laid out so it's stretched in that
+/// direction. Everything's in pixels.
+enum Field {
+  /// Straight across, along the unit vector `(dx, dy)`
+  Linear { dx: f32, dy: f32 },
+  /// Out from a point
+  Radial { cx: f32, cy: f32 },
+  /// Clockwise around a point, starting `start` radians clockwise from the right
+  Conic { cx: f32, cy: f32, start: f32 },
+  /// Out from a path through some points
+  Path { points: Vec<(f32, f32)> },
+}
+
+/// Sample noise laid out around a point, where `at` says where to sample for a point some angle around it. A layout
+/// that goes all the way around has a seam where the angle wraps, so two are blended, with their seams on opposite
+/// sides, and each fading out entirely at its own seam.
+fn around(noise: &impl Noise2D, angle: f32, at: impl Fn(f32) -> Pos) -> f32 {
+  let first = noise.get(at(angle.rem_euclid(TAU)));
+  let second = noise.get(at((angle + PI).rem_euclid(TAU)));
+  // all the second at the first's seam (an angle of 0), and all the first at the second's
+  let weight = (1.0 + angle.cos()) / 2.0;
+  first * (1.0 - weight) + second * weight
+}
+
+impl Field {
+  /// How far along the field a point is. It only means anything compared to `range`.
+  fn position(&self, x: f32, y: f32) -> f32 {
+    match self {
+      Field::Linear { dx, dy } => x * dx + y * dy,
+      Field::Radial { cx, cy } => (x - cx).hypot(y - cy),
+      Field::Conic { cx, cy, start } => ((y - cy).atan2(x - cx) - start).rem_euclid(TAU),
+      Field::Path { .. } => self.nearest(x, y).0,
+    }
   }
-  match s.parse::<f32>() {
-    Ok(i) => Ok(i / 100.0),
-    _ => Err(format!("{} is not a percent from 0 to 100", s))
+
+  /// The lowest and highest positions anywhere in a `width` by `height` image.
+  fn range(&self, width: u32, height: u32) -> (f32, f32) {
+    let (w, h) = (width as f32, height as f32);
+    let corners = [(0.0, 0.0), (w, 0.0), (0.0, h), (w, h)];
+    let corners = corners.iter().map(|&(x, y)| self.position(x, y));
+    match self {
+      // a straight blend is furthest along at a corner
+      Field::Linear { .. } => extremes(corners),
+      Field::Conic { .. } => (0.0, TAU),
+      // nearest where the image is closest to the center, which is the center itself if it's inside
+      Field::Radial { cx, cy } => (self.position(cx.clamp(0.0, w), cy.clamp(0.0, h)), extremes(corners).1),
+      // the farthest anything gets from a path can be anywhere, so check everywhere pixels meet
+      Field::Path { .. } => {
+        let positions = Renderer::new().quiet()
+          .render(width + 1, height + 1, |x, y| Luma([self.position(x as f32, y as f32)]));
+        extremes(positions.pixels().map(|px| px.0[0]))
+      }
+    }
   }
-}
 
-fn lerp(from: u8, to: u8, amt: f32) -> u8 {
-  let from_scaled = from as f32 * (1.0 - amt);
-  let to_scaled = to as f32 * amt;
-  let sum = from_scaled + to_scaled;
-  return sum as u8;
+  /// How far a point is from the path, and how far along the path the closest point on it is.
+  fn nearest(&self, x: f32, y: f32) -> (f32, f32) {
+    let points = match self {
+      Field::Path { points } => points,
+      _ => unreachable!("only paths have a nearest point"),
+    };
+    let (mut best, mut along, mut travelled) = (f32::INFINITY, 0.0, 0.0);
+    for pair in points.windows(2) {
+      let ((ax, ay), (bx, by)) = (pair[0], pair[1]);
+      let (sx, sy) = (bx - ax, by - ay);
+      let length = sx.hypot(sy);
+      let t = if length > 0.0 { (((x - ax) * sx + (y - ay) * sy) / (length * length)).clamp(0.0, 1.0) } else { 0.0 };
+      let distance = (x - ax - t * sx).hypot(y - ay - t * sy);
+      if distance < best {
+        best = distance;
+        along = travelled + t * length;
+      }
+      travelled += length;
+    }
+    (best, along)
+  }
+
+  /// Sample noise for a point, with the noise scaled so one unit covers `pix_sz` pixels, and stretched by `stretch`
+  /// in the direction the blend goes. Around a path, the noise is laid out along the path, so it may not line up on
+  /// the inside of sharp bends, where which part of the path is closest jumps.
+  fn sample(&self, noise: &impl Noise2D, x: f32, y: f32, pix_sz: f32, stretch: f32) -> f32 {
+    match self {
+      Field::Linear { dx, dy } => {
+        let (along, across) = (x * dx + y * dy, y * dx - x * dy);
+        noise.get(Pos::of(along / pix_sz / stretch, across / pix_sz))
+      }
+      Field::Radial { cx, cy } => {
+        let radius = (x - cx).hypot(y - cy);
+        around(noise, (y - cy).atan2(x - cx), |angle| Pos::of(radius / pix_sz / stretch, radius * angle / pix_sz))
+      }
+      Field::Conic { cx, cy, .. } => {
+        let radius = (x - cx).hypot(y - cy);
+        around(noise, (y - cy).atan2(x - cx), |angle| Pos::of(radius * angle / pix_sz / stretch, radius / pix_sz))
+      }
+      Field::Path { .. } => {
+        let (distance, along) = self.nearest(x, y);
+        noise.get(Pos::of(distance / pix_sz / stretch, along / pix_sz))
+      }
+    }
+  }
 }
 
 pub struct Mottler;
@@ -42,139 +137,171 @@ impl super::Gen for Mottler {
     fn command(&self) -> &'static str { "mottle" }
     fn category(&self) -> super::Category { super::Category::Project }
     fn about(&self) -> &'static str { "Blend two images together by picking pixels based on 2D noise" }
-    fn setup_cmd<'a, 'b>(&self, app: App<'a, 'b>) -> App<'a, 'b> {
-        app
-          .arg(Arg::with_name("from")
-            .short("f")
-            .long("from")
-            .takes_value(true)
-            .help("The image being blended 'from' (visible on the left or top)")
-            .required(true))
-          .arg(Arg::with_name("to")
-            .short("t")
-            .long("to")
-            .takes_value(true)
-            .help("The image being blended 'to' (visible on the right or bottom)")
-            .required(true))
-          .arg(Arg::with_name("vertical")
-            .short("U")
-            .long("vertical")
-            .help("Render the 'gradient' from top to bottom, instead of left to right."))
-          .arg(Arg::with_name("start")
-            .short("s")
-            .long("start")
-            .validator(validate_pct)
-            .default_value("10%")
-            .help("The starting point for the gradient, as a percentage of image size"))
-          .arg(Arg::with_name("end")
-            .short("e")
-            .long("end")
-            .validator(validate_pct)
-            .default_value("90%")
-            .help("The ending point for the gradient, as a percentage of image size"))
-          .arg(Arg::with_name("algorithm")
-            .short("a")
-            .long("algorithm")
-            .possible_values(&["perlin", "worley"])
-            .default_value("worley")
-            .help("The algorithm to generate noise with"))
-          .arg(Arg::with_name("algo-scale")
-            .long("scale")
-            .default_value("2.5%")
-            .help("How much of the image's width should be covered by one unit in the noise sampling space"))
-          .arg(Arg::with_name("algo-stretch")
-            .long("stretch")
-            .default_value("2")
-            .help("How much more to scale the noise sampling space in the gradient direction"))
-          .arg(Arg::with_name("algo-sharp")
-            .long("sharp")
-            .help("If provided, the mottling will use a hard cutoff rather than a smooth blend"))
+    fn aliases(&self) -> &'static [&'static str] { &["blend"] }
+    fn tags(&self) -> &'static [&'static str] { &["image input", "noise"] }
+    fn params(&self) -> Vec<Param> {
+        vec![
+          Param::new("from", Kind::Path)
+            .short('f')
+            .help("The image being blended 'from' (visible where the gradient starts)")
+            .required(),
+          Param::new("to", Kind::Path)
+            .short('t')
+            .help("The image being blended 'to' (visible where the gradient ends)")
+            .required(),
+          Param::new("direction", Kind::Enum(&["horizontal", "vertical", "angle", "radial", "conic", "path"]))
+            .short('d')
+            .default("horizontal")
+            .help("Which way the gradient goes: left to right, top to bottom, at --angle, out from --center, \
+              clockwise around --center, or out from --path"),
+          Param::new("vertical", Kind::Flag)
+            .short('U')
+            .help("Render the 'gradient' from top to bottom, instead of left to right (like --direction vertical)"),
+          Param::new("angle", Kind::Float { min: None, max: None })
+            .default("0")
+            .help("For an angled gradient, which way it goes, in degrees clockwise from left to right; for a conic \
+              one, where it starts, in degrees clockwise from the right"),
+          Param::new("center", Kind::Point)
+            .default("50%,50%")
+            .help("The point radial and conic gradients go around, as percentages of the image's width and height"),
+          Param::new("path", Kind::Points)
+            .help("The path a path gradient goes out from, as points like 10%,50%;50%,20%;90%,50%"),
+          Param::new("start", Kind::Percent { min: Some(0.0), max: Some(100.0) })
+            .short('s')
+            .default("10%")
+            .help("The starting point for the gradient, as a percentage of the way along it"),
+          Param::new("end", Kind::Percent { min: Some(0.0), max: Some(100.0) })
+            .short('e')
+            .default("90%")
+            .help("The ending point for the gradient, as a percentage of the way along it"),
+          Param::new("algorithm", Kind::Enum(&["worley"]))
+            .short('a')
+            .default("worley")
+            .help("The algorithm to generate noise with"),
+          Param::new("scale", Kind::Percent { min: None, max: None })
+            .default("2.5%")
+            .help("How much of the image's width should be covered by one unit in the noise sampling space"),
+          Param::new("stretch", Kind::Float { min: None, max: None })
+            .default("2")
+            .help("How much more to scale the noise sampling space in the gradient's direction"),
+          Param::new("sharp", Kind::Flag)
+            .help("If provided, the mottling will use a hard cutoff rather than a smooth blend"),
+        ]
     }
-    fn run(&self, opts: &ArgMatches, seed: &[u8], output: &mut dyn io::Write) -> super::Result<()> {
-      let img_from_path = opts.value_of("from").unwrap();
-      let img_from = image::io::Reader::open(img_from_path)?.decode()?;
-      println!("Opened {}", img_from_path);
-      let img_to_path = opts.value_of("to").unwrap();
-      let img_to = image::io::Reader::open(img_to_path)?.decode()?;
-      println!("Opened {}", img_to_path);
+    fn raster(&self, params: &Params, seed: &[u8]) -> super::Result<Option<Box<dyn Raster>>> {
+      let (img_from, img_to) = {
+        let _span = Span::enter("decode");
+        (super::load_image(params.path("from"))?, super::load_image(params.path("to"))?)
+      };
+      let (img_from_path, img_to_path) = (params.path("from").display(), params.path("to").display());
+      log::debug!("Opened {} and {}", img_from_path, img_to_path);
 
       if img_from.dimensions() != img_to.dimensions() {
-        return Err(super::GenFail::BadArg(format!(
+        return Err(super::GenFail::Data(format!(
           "{} {:?} and {} {:?} are not the same size",
           img_from_path, img_from.dimensions(),
           img_to_path, img_to.dimensions()
         )))
       }
 
-      if opts.is_present("vertical") {
-        return Err(super::GenFail::BadArg(format!(
-          "--vertical not supported yet, rotate the images yourself"
-        )));
-      }
-
       let (width, height) = img_from.dimensions();
-      let width = width as usize;
-      let height = height as usize;
 
-      let pix_pct = decode_pct(opts.value_of("algo-scale").unwrap()).unwrap();
+      let direction = match (params.choice("direction"), params.flag("vertical")) {
+        (direction, false) => direction,
+        ("horizontal", true) | ("vertical", true) => "vertical",
+        (direction, true) => {
+          return Err(super::GenFail::BadArg(format!("--vertical and --direction {} don't go together", direction)));
+        }
+      };
+      let (cx, cy) = params.point("center");
+      let (cx, cy) = (cx as f32 * width as f32, cy as f32 * height as f32);
+      let angle = (params.float("angle") as f32).to_radians();
+      let field = match direction {
+        "horizontal" => Field::Linear { dx: 1.0, dy: 0.0 },
+        "vertical" => Field::Linear { dx: 0.0, dy: 1.0 },
+        "angle" => Field::Linear { dx: angle.cos(), dy: angle.sin() },
+        "radial" => Field::Radial { cx, cy },
+        "conic" => Field::Conic { cx, cy, start: angle },
+        "path" => {
+          let points = match params.get("path") {
+            Some(_) => params.points("path"),
+            None => return Err(super::GenFail::BadArg("a path gradient needs a --path".to_string())),
+          };
+          if points.len() < 2 {
+            return Err(super::GenFail::BadArg("a path needs at least two points".to_string()));
+          }
+          let points = points.iter().map(|&(x, y)| (x as f32 * width as f32, y as f32 * height as f32)).collect();
+          Field::Path { points }
+        }
+        _ => unreachable!("Choices checked by the schema"),
+      };
+      let (lowest, highest) = {
+        let _span = Span::enter("measure the gradient");
+        field.range(width, height)
+      };
+
+      let pix_pct = params.percent("scale") as f32;
       let pix_sz = width as f32 * pix_pct;
 
-      let dir_stretch: f32 = opts.value_of("algo-stretch").unwrap().parse().unwrap();
+      let dir_stretch = params.float("stretch") as f32;
+
+      let sharp = params.flag("sharp");
 
-      let sharp = opts.is_present("algo-sharp");
+      let start_pct = params.percent("start") as f32;
+      let start = lowest + (highest - lowest) * start_pct;
 
-      let start_pct = decode_pct(opts.value_of("start").unwrap()).unwrap();
-      let start = width as f32 * start_pct;
-      
-      let end_pct = decode_pct(opts.value_of("end").unwrap()).unwrap();
-      let end = width as f32 * end_pct;
+      let end_pct = params.percent("end") as f32;
+      let end = lowest + (highest - lowest) * end_pct;
 
-      let noise = match opts.value_of("algorithm").unwrap() {
-        "perlin" => unimplemented!("perlin noise"),
+      let noise = match params.choice("algorithm") {
         "worley" => Worley::new(seed),
-        _ => unreachable!("Option values set with clap"),
+        _ => unreachable!("Choices checked by the schema"),
       }.invert();
 
-      let mut rows = Vec::with_capacity(height);
-      let start_time = Instant::now();
-      rows.par_extend((0..rows.capacity()).into_par_iter().map(|row| {
-        let mut data_out = vec![0; width * 3];
-        for x in 0..width {
-          let pos = Pos::of(x as f32 / pix_sz / dir_stretch, row as f32 / pix_sz);
-          let progress = ((x as f32 - start) / (end - start)).clamp(0.0, 1.0);
-          let (r, g, b) = if sharp {
-            let (r, g, b, _) = if noise.get(pos) < 1.0 - progress {
-              &img_from
-            } else {
-              &img_to
-            }.get_pixel(x as u32, row as u32).channels4();
-            (r, g, b)
+      Ok(Some(Box::new(Pixels::new(width, height, move |x: f32, y: f32| {
+        let value = field.sample(&noise, x, y, pix_sz, dir_stretch);
+        let position = field.position(x, y);
+        // with nowhere to blend over, it's one image on one side and the other on the other
+        let progress = if (end - start).abs() <= f32::EPSILON {
+          if position < start { 0.0 } else { 1.0 }
+        } else {
+          ((position - start) / (end - start)).clamp(0.0, 1.0)
+        };
+        // the source images only have whole pixels
+        let (x, y) = (x as u32, y as u32);
+        if sharp {
+          let (r, g, b, _) = if value < 1.0 - progress {
+            &img_from
           } else {
-            let bias = progress * 2.0 - 1.0;
-            let weight = (noise.get(pos) + bias).clamp(0.0, 1.0);
-            let (fr, fg, fb, _) = img_from.get_pixel(x as u32, row as u32).channels4();
-            let (tr, tg, tb, _) = img_to.get_pixel(x as u32, row as u32).channels4();
-            (lerp(fr, tr, weight), lerp(fg, tg, weight), lerp(fb, tb, weight))
-          };
-          data_out[x*3 + 0] = r;
-          data_out[x*3 + 1] = g;
-          data_out[x*3 + 2] = b;
+            &img_to
+          }.get_pixel(x, y).channels4();
+          Rgb([r, g, b])
+        } else {
+          let bias = progress * 2.0 - 1.0;
+          let weight = (value + bias).clamp(0.0, 1.0);
+          let (fr, fg, fb, _) = img_from.get_pixel(x, y).channels4();
+          let (tr, tg, tb, _) = img_to.get_pixel(x, y).channels4();
+          Rgb([lerp(fr, tr, weight), lerp(fg, tg, weight), lerp(fb, tb, weight)])
         }
-        data_out
-      }));
-      let gen_time = Instant::now() - start_time;
-      println!("Took {}ms to generate", gen_time.as_millis());
-
-      let mut pixels = Vec::with_capacity(width * height * 3);
-      for row in rows {
-        pixels.extend(row);
-      }
-
-      let encoder = PngEncoder::new_with_quality(output, CompressionType::Rle, FilterType::Sub);
-      encoder.encode(&pixels, width as u32, height as u32, ColorType::Rgb8)?;
+      }))))
+    }
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
 
-      println!("Finished");
+  #[test]
+  fn radial_ranges_go_from_the_nearest_part_of_the_image_to_the_farthest_corner() {
+    assert_eq!(Field::Radial { cx: 10.0, cy: 10.0 }.range(40, 20), (0.0, 30.0f32.hypot(10.0)));
+    // off to the left, so the nearest is the left edge
+    assert_eq!(Field::Radial { cx: -30.0, cy: 10.0 }.range(40, 20), (30.0, 70.0f32.hypot(10.0)));
+    // off past a corner, so the nearest is that corner
+    assert_eq!(Field::Radial { cx: 43.0, cy: 24.0 }.range(40, 20), (5.0, 43.0f32.hypot(24.0)));
+  }
 
-      Ok(())
-    }
-}
\ No newline at end of file
+  #[test]
+  fn path_ranges_find_the_farthest_point_inside_the_image() {
+    let border = Field::Path { points: vec![(0.0, 0.0), (40.0, 0.0), (40.0, 20.0), (0.0, 20.0), (0.0, 0.0)] };
+    assert_eq!(border.range(40, 20), (0.0, 10.0));
+  }
+}
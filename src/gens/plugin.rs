@@ -0,0 +1,307 @@
+//! Generators loaded at runtime from dynamic libraries, so they don't have to live in hart itself.
+//!
+//! A plugin is a dynamic library (`.so`, `.dylib`, or `.dll`) exporting one function, `hart_plugin`, which takes no
+//! arguments and returns a pointer to an [`abi::Plugin`] describing it. Everything in [`abi`] is `#[repr(C)]`, so
+//! plugins can be written in anything that speaks the C ABI. In C, it looks like:
+//!
+//! ```c
+//! typedef struct { const char *name, *help; uint32_t kind; uint8_t short_name, required;
+//!                  const char *default_value; double min, max;
+//!                  const char *const *choices; size_t choice_count; } HartParam;
+//! typedef struct { const char *name, *value; } HartArg;
+//! typedef struct { uint32_t width, height, channels; uint8_t *data; } HartImage;
+//! typedef struct {
+//!   uint32_t abi_version;
+//!   const char *name, *about;
+//!   const HartParam *params; size_t param_count;
+//!   int32_t (*run)(const HartArg *args, size_t arg_count, const uint8_t *seed, size_t seed_len,
+//!                  HartImage *out, char *error, size_t error_len);
+//!   void (*free_image)(HartImage *image);
+//! } HartPlugin;
+//!
+//! const HartPlugin *hart_plugin(void);
+//! ```
+//!
+//! `abi_version` has to be [`abi::VERSION`]; plugins built for any other version are refused rather than guessed at.
+//! `run` may be called from any thread, and more than once at a time.
+
+use {
+  super::{Category, Gen, GenFail, Registry, Result},
+  crate::{
+    output::Output,
+    params::{Kind, Param, Params, RESERVED, RESERVED_SHORTS},
+  },
+  image::{GrayImage, RgbImage, RgbaImage},
+  libloading::Library,
+  std::{
+    env,
+    ffi::{CStr, CString, OsStr},
+    fs,
+    os::raw::c_char,
+    path::{Path, PathBuf},
+  },
+};
+
+/// The types shared between hart and its plugins.
+pub mod abi {
+  use std::os::raw::c_char;
+
+  /// The version of the plugin ABI this build of hart speaks. It changes whenever anything in here does.
+  pub const VERSION: u32 = 1;
+
+  /// The name of the function every plugin exports, which returns a pointer to its `Plugin`.
+  pub const ENTRY: &[u8] = b"hart_plugin\0";
+
+  /// The type of the function every plugin exports.
+  pub type Entry = unsafe extern "C" fn() -> *const Plugin;
+
+  /// `Param::kind` for a whole number, limited to `min..=max` unless they're NaN
+  pub const KIND_INT: u32 = 0;
+  /// `Param::kind` for a real number, limited to `min..=max` unless they're NaN
+  pub const KIND_FLOAT: u32 = 1;
+  /// `Param::kind` for a percentage, limited to `min..=max` percent unless they're NaN
+  pub const KIND_PERCENT: u32 = 2;
+  /// `Param::kind` for one of `choices`
+  pub const KIND_ENUM: u32 = 3;
+  /// `Param::kind` for a path to a file, or `-` for stdin
+  pub const KIND_PATH: u32 = 4;
+  /// `Param::kind` for a switch
+  pub const KIND_FLAG: u32 = 5;
+  /// `Param::kind` for a width and height
+  pub const KIND_SIZE: u32 = 6;
+
+  /// A description of a plugin. It has to live, unchanged, for as long as the plugin is loaded.
+  #[repr(C)]
+  pub struct Plugin {
+    /// Always `VERSION`, or the plugin won't be loaded
+    pub abi_version: u32,
+    /// The plugin's command, run as `hart plugin <name>`
+    pub name: *const c_char,
+    /// A short, human-friendly description
+    pub about: *const c_char,
+    pub params: *const Param,
+    pub param_count: usize,
+    /// Run the plugin with every parameter's value in text form, filling in `out` on success and returning 0. On
+    /// failure it returns anything else, and writes a nul-terminated message of up to `error_len` bytes to `error`.
+    pub run: unsafe extern "C" fn(
+      args: *const Arg, arg_count: usize, seed: *const u8, seed_len: usize, out: *mut Image, error: *mut c_char,
+      error_len: usize,
+    ) -> i32,
+    /// Free an image made by `run`, once hart has copied it
+    pub free_image: unsafe extern "C" fn(image: *mut Image),
+  }
+
+  /// One parameter a plugin takes, the same as `crate::params::Param`.
+  #[repr(C)]
+  pub struct Param {
+    pub name: *const c_char,
+    pub help: *const c_char,
+    /// One of the `KIND_` constants
+    pub kind: u32,
+    /// An ASCII abbreviation for the command line, or 0 for none
+    pub short: u8,
+    /// Nonzero if a value has to be given
+    pub required: u8,
+    /// The default value in text form, or null for none
+    pub default: *const c_char,
+    pub min: f64,
+    pub max: f64,
+    pub choices: *const *const c_char,
+    pub choice_count: usize,
+  }
+
+  /// A parameter's value, in text form.
+  #[repr(C)]
+  pub struct Arg {
+    pub name: *const c_char,
+    pub value: *const c_char,
+  }
+
+  /// An image a plugin made: 8-bit, row-major, with 1 (gray), 3 (RGB), or 4 (RGBA) channels.
+  #[repr(C)]
+  pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub channels: u32,
+    pub data: *mut u8,
+  }
+
+  // Plugins are immutable once they're loaded, and have to be safe to run from any thread, so these can be shared.
+  unsafe impl Sync for Plugin {}
+  unsafe impl Sync for Param {}
+}
+
+/// Where plugins are loaded from: `$HART_PLUGINS` if it's set, or else `hart/plugins` in the XDG data directory.
+pub fn default_dir() -> Option<PathBuf> {
+  if let Some(dir) = env::var_os("HART_PLUGINS") {
+    return Some(PathBuf::from(dir));
+  }
+  let data = env::var_os("XDG_DATA_HOME").map(PathBuf::from)
+    .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("share")))?;
+  Some(data.join("hart").join("plugins"))
+}
+
+/// Read a string a plugin handed over. It lives as long as the plugin, which is never unloaded, so it's `'static`.
+unsafe fn plugin_str(ptr: *const c_char, what: &str) -> Result<&'static str> {
+  if ptr.is_null() {
+    return Err(GenFail::Plugin(format!("the plugin has no {}", what)));
+  }
+  CStr::from_ptr(ptr).to_str().map_err(|_| GenFail::Plugin(format!("the plugin's {} isn't UTF-8", what)))
+}
+
+/// Translate one of a plugin's parameters into the usual description.
+unsafe fn plugin_param(param: &abi::Param) -> Result<Param> {
+  let name = plugin_str(param.name, "parameter name")?;
+  let bound = |n: f64| if n.is_nan() { None } else { Some(n) };
+  let kind = match param.kind {
+    abi::KIND_INT => Kind::Int { min: bound(param.min).map(|n| n as i64), max: bound(param.max).map(|n| n as i64) },
+    abi::KIND_FLOAT => Kind::Float { min: bound(param.min), max: bound(param.max) },
+    abi::KIND_PERCENT => Kind::Percent { min: bound(param.min), max: bound(param.max) },
+    abi::KIND_ENUM => {
+      let mut choices = Vec::with_capacity(param.choice_count);
+      for i in 0..param.choice_count {
+        choices.push(plugin_str(*param.choices.add(i), "choice")?);
+      }
+      Kind::Enum(Box::leak(choices.into_boxed_slice()))
+    }
+    abi::KIND_PATH => Kind::Path,
+    abi::KIND_FLAG => Kind::Flag,
+    abi::KIND_SIZE => Kind::Size,
+    other => return Err(GenFail::Plugin(format!("{} has an unknown kind, {}", name, other))),
+  };
+  let mut out = Param::new(name, kind);
+  if !param.help.is_null() {
+    out = out.help(plugin_str(param.help, "help")?);
+  }
+  if param.short.is_ascii_alphanumeric() {
+    out = out.short(param.short as char);
+  }
+  if !param.default.is_null() {
+    out = out.default(plugin_str(param.default, "default")?);
+  }
+  if param.required != 0 {
+    out = out.required();
+  }
+  Ok(out)
+}
+
+/// A generator living in a dynamic library.
+pub struct PluginGen {
+  plugin: &'static abi::Plugin,
+  name: &'static str,
+  about: &'static str,
+  params: Vec<Param>,
+}
+
+impl PluginGen {
+  /// Load a plugin from a dynamic library. The library is never unloaded, even if the generator is dropped, since
+  /// its strings are handed out as `'static`.
+  ///
+  /// # Safety
+  ///
+  /// Loading a library runs its initialization code, and the plugin's description is trusted to be laid out the way
+  /// `abi` says, so only load plugins you trust.
+  pub unsafe fn load(path: &Path) -> Result<PluginGen> {
+    let library = Library::new(path).map_err(|e| GenFail::Plugin(e.to_string()))?;
+    let entry = *library.get::<abi::Entry>(abi::ENTRY).map_err(|e| GenFail::Plugin(e.to_string()))?;
+    std::mem::forget(library);
+    let plugin = entry();
+    if plugin.is_null() {
+      return Err(GenFail::Plugin("the plugin didn't describe itself".to_string()));
+    }
+    // only the version can be trusted until it's been checked
+    let version = *(plugin as *const u32);
+    if version != abi::VERSION {
+      return Err(GenFail::Plugin(format!("the plugin is for version {} of the ABI, not {}", version, abi::VERSION)));
+    }
+    let plugin = &*plugin;
+    let mut params = Vec::with_capacity(plugin.param_count);
+    for i in 0..plugin.param_count {
+      let param = plugin_param(&*plugin.params.add(i))?;
+      if RESERVED.contains(&param.name) || param.short.is_some_and(|short| RESERVED_SHORTS.contains(&short)) {
+        return Err(GenFail::Plugin(format!("the plugin's {} parameter clashes with one of hart's own", param.name)));
+      }
+      params.push(param);
+    }
+    Ok(PluginGen {
+      plugin,
+      name: plugin_str(plugin.name, "name")?,
+      about: plugin_str(plugin.about, "description")?,
+      params,
+    })
+  }
+}
+
+impl Gen for PluginGen {
+  fn category(&self) -> Category { Category::Plugin }
+  fn command(&self) -> &'static str { self.name }
+  fn about(&self) -> &'static str { self.about }
+  fn params(&self) -> Vec<Param> { self.params.clone() }
+  fn run(&self, params: &Params, seed: &[u8]) -> Result<Output> {
+    let text: Vec<(CString, CString)> = params.to_text().into_iter()
+      .map(|(name, value)| (CString::new(name), CString::new(value)))
+      .map(|(name, value)| Ok((name?, value?)))
+      .collect::<std::result::Result<_, std::ffi::NulError>>()
+      .map_err(|_| GenFail::BadArg("parameters can't contain nul bytes".to_string()))?;
+    let args: Vec<abi::Arg> = text.iter().map(|(name, value)| abi::Arg { name: name.as_ptr(), value: value.as_ptr() })
+      .collect();
+    let mut image = abi::Image { width: 0, height: 0, channels: 0, data: std::ptr::null_mut() };
+    let mut error = [0 as c_char; 1024];
+
+    let status = unsafe {
+      (self.plugin.run)(args.as_ptr(), args.len(), seed.as_ptr(), seed.len(), &mut image, error.as_mut_ptr(),
+        error.len())
+    };
+    if status != 0 {
+      // make sure it's terminated, however badly behaved the plugin is
+      error[error.len() - 1] = 0;
+      let message = unsafe { CStr::from_ptr(error.as_ptr()) }.to_string_lossy();
+      return Err(GenFail::Plugin(format!("{} failed: {}", self.name, message)));
+    }
+
+    let (width, height) = (image.width, image.height);
+    let len = width as usize * height as usize * image.channels as usize;
+    let data = if image.data.is_null() {
+      vec![]
+    } else {
+      unsafe { std::slice::from_raw_parts(image.data, len) }.to_vec()
+    };
+    let channels = image.channels;
+    unsafe { (self.plugin.free_image)(&mut image) };
+    let wrong = || GenFail::Plugin(format!("{} made a malformed {}x{}x{} image", self.name, width, height, channels));
+    let out = match channels {
+      1 => GrayImage::from_raw(width, height, data).ok_or_else(wrong)?.into(),
+      3 => RgbImage::from_raw(width, height, data).ok_or_else(wrong)?.into(),
+      4 => RgbaImage::from_raw(width, height, data).ok_or_else(wrong)?.into(),
+      _ => return Err(wrong()),
+    };
+    Ok(Output::Image(out))
+  }
+}
+
+/// Load every plugin in a directory into a registry, returning the ones that couldn't be loaded and why. A directory
+/// that doesn't exist just has no plugins in it.
+///
+/// # Safety
+///
+/// See `PluginGen::load`: every library in the directory is loaded, and trusted.
+pub unsafe fn load_dir(registry: &mut Registry, dir: &Path) -> Vec<(PathBuf, GenFail)> {
+  let entries = match fs::read_dir(dir) {
+    Ok(entries) => entries,
+    Err(_) => return vec![],
+  };
+  let mut failed = vec![];
+  let mut paths: Vec<_> = entries.filter_map(|entry| entry.ok().map(|e| e.path()))
+    .filter(|path| path.extension() == Some(OsStr::new(env::consts::DLL_EXTENSION)))
+    .collect();
+  // load in a consistent order, so which of two clashing plugins wins doesn't change from run to run
+  paths.sort();
+  for path in paths {
+    let loaded = PluginGen::load(&path)
+      .and_then(|gen| registry.register(Box::leak(Box::new(gen))).map_err(GenFail::Plugin));
+    if let Err(e) = loaded {
+      failed.push((path, e));
+    }
+  }
+  failed
+}
@@ -0,0 +1,79 @@
+//! The set of generators hart knows about. Frontends build their commands from a `Registry`, so adding a generator
+//! is just a matter of registering it.
+
+use {
+  super::{Category, Gen, Mottler, Test2D, TestAscii, WorleyGen},
+  std::collections::HashMap,
+};
+
+/// Generators, looked up by category and name (or alias).
+#[derive(Default)]
+pub struct Registry {
+  gens: Vec<&'static dyn Gen>,
+  by_name: HashMap<(Category, &'static str), usize>,
+}
+
+impl Registry {
+  /// A registry with nothing in it.
+  pub fn new() -> Registry {
+    Registry::default()
+  }
+
+  /// Every generator built into hart.
+  pub fn builtin() -> Registry {
+    Registry::new()
+      .with(&TestAscii)
+      .with(&Test2D)
+      .with(&WorleyGen)
+      .with(&Mottler)
+  }
+
+  /// Add a generator, or explain why it can't be added: its name or one of its aliases is already taken in its
+  /// category.
+  pub fn register(&mut self, gen: &'static dyn Gen) -> Result<(), String> {
+    let category = gen.category();
+    let names: Vec<_> = std::iter::once(gen.command()).chain(gen.aliases().iter().cloned()).collect();
+    if let Some(taken) = names.iter().find(|name| self.by_name.contains_key(&(category, **name))) {
+      return Err(format!("there's already a generator called {}/{}", category.name(), taken));
+    }
+    for name in names {
+      self.by_name.insert((category, name), self.gens.len());
+    }
+    self.gens.push(gen);
+    Ok(())
+  }
+
+  /// Add a generator, builder-style. Panics if it can't be added, so only use it for generators known not to clash.
+  pub fn with(mut self, gen: &'static dyn Gen) -> Registry {
+    if let Err(e) = self.register(gen) {
+      panic!("{}", e);
+    }
+    self
+  }
+
+  /// Every generator, in the order they were registered, including hidden ones.
+  pub fn all(&self) -> &[&'static dyn Gen] {
+    &self.gens
+  }
+
+  /// The generators that should be listed to users, i.e. everything not hidden.
+  pub fn visible(&self) -> impl Iterator<Item = &'static dyn Gen> + '_ {
+    self.gens.iter().cloned().filter(|gen| !gen.hidden())
+  }
+
+  /// The generators with a certain tag.
+  pub fn tagged<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = &'static dyn Gen> + 'a {
+    self.gens.iter().cloned().filter(move |gen| gen.tags().contains(&tag))
+  }
+
+  /// The categories with at least one generator in them, in their usual order.
+  pub fn categories(&self) -> Vec<Category> {
+    Category::all().iter().cloned().filter(|cat| self.gens.iter().any(|gen| gen.category() == *cat)).collect()
+  }
+
+  /// Find a generator by the names it's run by on the command line: its category and its command or an alias.
+  pub fn find(&self, category: &str, name: &str) -> Option<&'static dyn Gen> {
+    let category = Category::by_name(category)?;
+    self.by_name.get(&(category, name)).map(|&idx| self.gens[idx])
+  }
+}
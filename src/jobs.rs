@@ -0,0 +1,285 @@
+//! Job files: a whole render session written down as TOML, so it can be checked in and run again with `hart run`.
+//!
+//! A job file is a list of `[[job]]` tables, each naming a generator, its parameters, its seed, and where its output
+//! goes, plus an optional `[defaults]` table which every job starts from. Default parameters only go to the generators
+//! which take them:
+//!
+//! ```toml
+//! [defaults]
+//! params = { size = [1920, 1080] }
+//!
+//! [[job]]
+//! name = "cells"
+//! generator = "basic/worley"
+//! seed = "exhibition"
+//! output = "out/cells.png"
+//! params = { octaves = 3 }
+//! ```
+//!
+//! The other keys are named after the command line options: `seed-int`, `seed-hex`, `seed-b64`, and `seed-file` for
+//! other ways to give the seed, `format`, `depth`, `quality`, `png-compression`, and `png-filter` for the encoding,
+//! and `region = [x, y, w, h]`, `canvas = [w, h]`, and `stream = true` for how to render it. Relative paths, in the
+//! keys or in parameters, are relative to the job file.
+
+use {
+  crate::{
+    gens::{Gen, Registry, STDIO},
+    output::{self, Depth, Encoding, Format},
+    params::{self, Kind, Param, Params},
+    recipe::Recipe,
+    seed,
+    utils::render::{Tile, View},
+    VERSION,
+  },
+  std::{
+    convert::TryFrom as _,
+    fs,
+    path::{Path, PathBuf},
+  },
+  toml::{value::Table, Value},
+};
+
+/// The keys a job can have.
+const KEYS: &[&str] = &[
+  "name", "generator", "output", "params", "seed", "seed-int", "seed-hex", "seed-b64", "seed-file", "format", "depth",
+  "quality", "png-compression", "png-filter", "region", "canvas", "stream",
+];
+
+/// One generator invocation from a job file, ready to run.
+pub struct Job {
+  /// What to call the job in messages: its `name` if it has one, or its position in the file
+  pub name: String,
+  pub gen: &'static dyn Gen,
+  pub params: Params,
+  pub recipe: Recipe,
+  pub encoding: Encoding,
+  /// The canvas the region has to fit in, if one was given
+  pub canvas: Option<(u32, u32)>,
+  pub stream: bool,
+  pub output: PathBuf,
+  /// The files the generator reads, which have to be written first if another job writes them
+  pub inputs: Vec<PathBuf>,
+}
+
+/// Turn a TOML value into the text form parameters and options are parsed from. Pairs of numbers, like sizes, are
+/// written `WxH`.
+pub(crate) fn text(value: &Value) -> Result<String, String> {
+  match value {
+    Value::String(s) => Ok(s.clone()),
+    Value::Integer(i) => Ok(i.to_string()),
+    Value::Float(f) => Ok(f.to_string()),
+    Value::Boolean(b) => Ok(b.to_string()),
+    Value::Array(items) => match items.as_slice() {
+      [Value::Integer(width), Value::Integer(height)] => Ok(format!("{}x{}", width, height)),
+      _ => Err(format!("{} isn't a value hart understands", value)),
+    },
+    _ => Err(format!("{} isn't a value hart understands", value)),
+  }
+}
+
+/// Read a list of exactly `count` nonnegative integers, like a region.
+fn numbers(value: &Value, count: usize) -> Result<Vec<u32>, String> {
+  let bad = || format!("{} isn't a list of {} nonnegative integers", value, count);
+  let items = value.as_array().filter(|items| items.len() == count).ok_or_else(bad)?;
+  items.iter()
+    .map(|item| item.as_integer().and_then(|i| u32::try_from(i).ok()).ok_or_else(bad))
+    .collect()
+}
+
+/// A path from the job file, relative to the directory the file's in.
+fn path(dir: &Path, text: &str) -> PathBuf {
+  if text == STDIO {
+    PathBuf::from(text)
+  } else {
+    dir.join(text)
+  }
+}
+
+/// Read a table of parameters as text, with paths made relative to the job file's directory. Only the parameters
+/// `keep` says to are read.
+fn given_params(
+  params: Option<&Value>, schema: &[Param], dir: &Path, keep: impl Fn(&str) -> bool,
+) -> Result<Vec<(String, String)>, String> {
+  let given = match params {
+    None => return Ok(vec![]),
+    Some(Value::Table(given)) => given,
+    Some(other) => return Err(format!("params should be a table, not {}", other)),
+  };
+  given.iter()
+    .filter(|(name, _)| keep(name))
+    .map(|(name, value)| {
+      let value = text(value).map_err(|e| format!("{}: {}", name, e))?;
+      match schema.iter().find(|param| param.name == name) {
+        Some(param) if param.kind == Kind::Path => Ok((name.clone(), path(dir, &value).display().to_string())),
+        _ => Ok((name.clone(), value)),
+      }
+    })
+    .collect()
+}
+
+/// Build one job from its table, filling in anything it doesn't say from the defaults. Default parameters only go to
+/// generators which take them, so they can be shared between different generators.
+fn job(defaults: &Table, table: &Table, dir: &Path, registry: &Registry) -> Result<Job, String> {
+  if let Some(key) = defaults.keys().chain(table.keys()).find(|key| !KEYS.contains(&key.as_str())) {
+    return Err(format!("there's no option called {}", key));
+  }
+  let mut defaults = defaults.clone();
+  let default_params = defaults.remove("params");
+  let table = &{
+    defaults.extend(table.iter().map(|(key, value)| (key.clone(), value.clone())));
+    defaults
+  };
+  let string = |key: &str| table.get(key).map(text).transpose();
+
+  let generator = string("generator")?.ok_or("it doesn't say which generator to run")?;
+  let gen = generator.split_once('/')
+    .and_then(|(category, command)| registry.find(category, command))
+    .ok_or_else(|| format!("there's no generator called {}", generator))?;
+  let schema = gen.params();
+
+  let takes = |name: &str| schema.iter().any(|param| param.name == name);
+  let mut given = given_params(default_params.as_ref(), &schema, dir, takes)?;
+  given.extend(given_params(table.get("params"), &schema, dir, |_| true)?);
+  let params = params::resolve(&schema, given)?;
+  let inputs = schema.iter()
+    .filter(|param| param.kind == Kind::Path)
+    .filter_map(|param| params.get(param.name).map(|_| params.path(param.name).to_path_buf()))
+    .collect();
+
+  let seeds: Vec<_> = ["seed", "seed-int", "seed-hex", "seed-b64", "seed-file"].iter()
+    .filter(|key| table.contains_key(**key))
+    .collect();
+  let seed = match seeds.as_slice() {
+    [] => return Err("it doesn't have a seed".to_string()),
+    [_, _, ..] => {
+      let keys: Vec<_> = seeds.iter().map(|key| **key).collect();
+      return Err(format!("it has more than one seed: {}", keys.join(", ")));
+    }
+    [key] => {
+      let value = string(key)?.unwrap();
+      match **key {
+        "seed" => value.into_bytes(),
+        "seed-int" => seed::from_int(&value)?,
+        "seed-hex" => seed::from_hex(&value)?,
+        "seed-b64" => seed::from_b64(&value)?,
+        _ => {
+          let file = path(dir, &value);
+          fs::read(&file).map_err(|e| format!("couldn't read the seed from {}: {}", file.display(), e))?
+        }
+      }
+    }
+  };
+
+  let output = path(dir, &string("output")?.ok_or("it doesn't say where to write its output")?);
+  let format = match string("format")? {
+    Some(name) => Some(Format::by_name(&name).ok_or_else(|| format!("there's no image format called {}", name))?),
+    None => Format::from_path(&output),
+  };
+  let mut encoding = Encoding::new(format.unwrap_or(Format::Png));
+  // not every generator makes images, so an extension we don't know (like .txt) is only a problem for images
+  encoding.guessed = format.is_none() && output.extension().is_some();
+  if let Some(name) = string("depth")? {
+    encoding.depth = Depth::by_name(&name).ok_or_else(|| format!("there's no depth called {}", name))?;
+  }
+  if let Some(quality) = string("quality")? {
+    encoding.jpeg_quality = quality.parse().ok()
+      .filter(|q| (1..=100).contains(q))
+      .ok_or_else(|| format!("{} is not a quality from 1 to 100", quality))?;
+  }
+  if let Some(name) = string("png-compression")? {
+    encoding.png_compression = output::png_compression(&name)
+      .ok_or_else(|| format!("{} isn't one of {}", name, output::PNG_COMPRESSIONS.join(", ")))?;
+  }
+  if let Some(name) = string("png-filter")? {
+    encoding.png_filter = output::png_filter(&name)
+      .ok_or_else(|| format!("{} isn't one of {}", name, output::PNG_FILTERS.join(", ")))?;
+  }
+  encoding.check()?;
+
+  let view = match table.get("region").map(|region| numbers(region, 4)).transpose()? {
+    Some(nums) if nums[2] == 0 || nums[3] == 0 => return Err("the region has to have some area".to_string()),
+    Some(nums) => Some(View::region(Tile { x: nums[0], y: nums[1], width: nums[2], height: nums[3] })),
+    None => None,
+  };
+  let canvas = match table.get("canvas").map(|canvas| numbers(canvas, 2)).transpose()? {
+    Some(nums) if nums[0] == 0 || nums[1] == 0 => return Err("the canvas has to have some area".to_string()),
+    Some(nums) => Some((nums[0], nums[1])),
+    None => None,
+  };
+  let stream = match table.get("stream") {
+    None => false,
+    Some(Value::Boolean(stream)) => *stream,
+    Some(other) => return Err(format!("stream should be true or false, not {}", other)),
+  };
+
+  Ok(Job {
+    name: string("name")?.unwrap_or_default(),
+    gen,
+    recipe: Recipe { version: VERSION.to_string(), generator, params: params.to_text(), seed, view },
+    params,
+    encoding,
+    canvas,
+    stream,
+    output,
+    inputs,
+  })
+}
+
+/// Read every job out of a job file's contents. `dir` is the directory the file is in, which relative paths are
+/// relative to.
+pub fn parse(contents: &str, dir: &Path, registry: &Registry) -> Result<Vec<Job>, String> {
+  let file: Table = toml::from_str(contents).map_err(|e| e.to_string())?;
+  if let Some(key) = file.keys().find(|key| *key != "job" && *key != "defaults") {
+    return Err(format!("there's no section called {}", key));
+  }
+  let defaults = match file.get("defaults") {
+    None => Table::new(),
+    Some(Value::Table(defaults)) => defaults.clone(),
+    Some(_) => return Err("defaults should be a table".to_string()),
+  };
+  let tables = match file.get("job") {
+    None => return Err("there aren't any jobs in it".to_string()),
+    Some(Value::Array(tables)) => tables,
+    Some(_) => return Err("jobs should be written [[job]]".to_string()),
+  };
+  tables.iter().enumerate()
+    .map(|(i, table)| {
+      let describe = |e| format!("job {}: {}", i + 1, e);
+      let table = table.as_table().ok_or_else(|| describe("jobs should be written [[job]]".to_string()))?;
+      let mut job = job(&defaults, table, dir, registry).map_err(describe)?;
+      if job.name.is_empty() {
+        job.name = format!("job {}", i + 1);
+      }
+      Ok(job)
+    })
+    .collect()
+}
+
+/// Put jobs in the order they have to run in: each stage only reads inputs which earlier stages wrote, so the jobs
+/// within a stage can all run at once. Fails if two jobs write the same file, or jobs need each other's outputs.
+pub fn stages(jobs: &[Job]) -> Result<Vec<Vec<usize>>, String> {
+  for (i, job) in jobs.iter().enumerate() {
+    if let Some(other) = jobs[..i].iter().find(|other| other.output == job.output) {
+      return Err(format!("{} and {} both write {}", other.name, job.name, job.output.display()));
+    }
+  }
+  let needs: Vec<Vec<usize>> = jobs.iter()
+    .map(|job| (0..jobs.len()).filter(|&i| job.inputs.contains(&jobs[i].output)).collect())
+    .collect();
+  let mut done = vec![false; jobs.len()];
+  let mut stages = vec![];
+  while done.iter().any(|done| !done) {
+    let stage: Vec<usize> = (0..jobs.len())
+      .filter(|&i| !done[i] && needs[i].iter().all(|&need| done[need]))
+      .collect();
+    if stage.is_empty() {
+      let stuck: Vec<_> = (0..jobs.len()).filter(|&i| !done[i]).map(|i| jobs[i].name.as_str()).collect();
+      return Err(format!("{} need each other's outputs, so none of them can go first", stuck.join(", ")));
+    }
+    for &i in &stage {
+      done[i] = true;
+    }
+    stages.push(stage);
+  }
+  Ok(stages)
+}
@@ -0,0 +1,41 @@
+//! Randomly generated art.
+//!
+//! The `hart` binary is a thin command line wrapper around this library, which has everything needed to drive the
+//! generators from your own code, or to build new ones out of the same pieces:
+//!
+//! - [`gens`] has the art generators themselves, the [`Gen`] trait they all implement, and the [`Registry`] of them.
+//! - [`output`] has the images generators produce, and encodes them.
+//! - [`params`] describes the parameters generators take, and checks values against them.
+//! - [`noise`] has the noise functions the generators are built from, and the [`Noise2D`](noise::Noise2D) trait.
+//! - [`ForeveRNG`] is the random number generator everything is seeded with.
+//! - [`seed`] and [`recipe`] handle writing seeds down and recording how an image was made.
+//! - [`batch`] expands many seeds and parameter sweeps into variants to render all at once, and [`sheet`] lays
+//!   thumbnails of them out in a grid.
+//! - [`jobs`] reads job files, which describe a whole session of renders.
+//! - [`schema`] describes generators and their parameters as JSON, for other programs to build on.
+//! - [`config`] reads defaults for options and parameters from config files and the environment.
+//! - [`run`] runs generators and writes their output, the way the `hart` binary does, through a [`Sink`] so failures
+//!   never leave half-written files behind.
+
+pub mod batch;
+pub mod config;
+pub mod gens;
+pub mod jobs;
+pub mod output;
+pub mod params;
+pub mod recipe;
+pub mod run;
+pub mod schema;
+pub mod seed;
+pub mod sheet;
+pub mod sink;
+pub mod utils;
+
+pub use {
+  gens::{Category, Context, Gen, GenFail, Registry},
+  sink::Sink,
+  utils::{noise, ForeveRNG},
+};
+
+/// The version of hart, as recorded in recipes.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
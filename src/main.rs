@@ -2,21 +2,220 @@ use {
   std::{
     collections::HashMap,
     fs,
-    io::{
-      self,
-      BufWriter,
-    },
+    io::{self, IsTerminal as _, Read as _},
+    path::{Path, PathBuf},
+    process,
     str::FromStr as _,
   },
   clap::{
-    App, AppSettings, Arg, SubCommand, Shell
+    App, AppSettings, Arg, ArgGroup, ArgMatches, SubCommand, Shell
+  },
+  hart::{
+    batch::{self, Variant},
+    config::{self, Config},
+    gens::{self, plugin, GenFail, Registry, STDIO},
+    params::{self, Kind, Param},
+    output::{self, Depth, Encoding, Format},
+    recipe::Recipe,
+    run::{self, Exit, Failure, OrExit as _},
+    schema,
+    seed,
+    utils::render::{Tile, View},
+    Sink,
+    VERSION,
   },
 };
 
-mod gens;
-mod utils;
+/// Set up logging at the level asked for by -q or -v, or by the config if neither was given.
+fn init_logging(args: &ArgMatches, config: &Config) {
+  let setting = |name| config.option(name).map(|setting| setting.value.as_str());
+  let verbose = match args.occurrences_of("verbose") {
+    // the unwrap is safe: the config's already been checked
+    0 => setting("verbose").map_or(0, |verbose| verbose.parse().unwrap()),
+    verbose => verbose,
+  };
+  let quiet = args.is_present("quiet") || (verbose == 0 && setting("quiet") == Some("true"));
+  let level = match (quiet, verbose) {
+    (true, _) => log::LevelFilter::Warn,
+    (false, 0) => log::LevelFilter::Info,
+    (false, 1) => log::LevelFilter::Debug,
+    (false, _) => log::LevelFilter::Trace,
+  };
+  run::init_logging(level);
+}
+
+fn validate_hex(s: String) -> Result<(), String> {
+  seed::from_hex(&s).map(|_| ())
+}
+
+fn validate_b64(s: String) -> Result<(), String> {
+  seed::from_b64(&s).map(|_| ())
+}
+
+fn validate_int(s: String) -> Result<(), String> {
+  seed::from_int(&s).map(|_| ())
+}
+
+fn validate_dimension(s: String) -> Result<(), String> {
+  match s.parse::<u32>() {
+    Ok(i) if i > 0 => Ok(()),
+    _ => Err(format!("{} is not a positive integer in range", s)),
+  }
+}
+
+/// Parse exactly `count` comma-separated nonnegative integers, like `x,y,w,h`.
+fn parse_u32s(s: &str, count: usize) -> Result<Vec<u32>, String> {
+  let nums = s.split(',')
+    .map(|n| n.trim().parse::<u32>().map_err(|_| format!("{} is not a nonnegative integer", n)))
+    .collect::<Result<Vec<_>, _>>()?;
+  if nums.len() != count {
+    return Err(format!("expected {} comma-separated numbers, got {}", count, nums.len()));
+  }
+  Ok(nums)
+}
+
+fn parse_region(s: &str) -> Result<Tile, String> {
+  let nums = parse_u32s(s, 4)?;
+  if nums[2] == 0 || nums[3] == 0 {
+    return Err("the region has to have some area".to_string());
+  }
+  Ok(Tile { x: nums[0], y: nums[1], width: nums[2], height: nums[3] })
+}
+
+fn parse_canvas(s: &str) -> Result<(u32, u32), String> {
+  let nums = parse_u32s(s, 2)?;
+  if nums[0] == 0 || nums[1] == 0 {
+    return Err("the canvas has to have some area".to_string());
+  }
+  Ok((nums[0], nums[1]))
+}
+
+fn validate_zoom(s: String) -> Result<(), String> {
+  match s.parse::<u32>() {
+    Ok(z) if z <= run::MAX_ZOOM => Ok(()),
+    _ => Err(format!("{} is not a zoom level from 0 to {}", s, run::MAX_ZOOM)),
+  }
+}
+
+fn validate_quality(s: String) -> Result<(), String> {
+  match s.parse::<u8>() {
+    Ok(q) if (1..=100).contains(&q) => Ok(()),
+    _ => Err(format!("{} is not a quality from 1 to 100", s)),
+  }
+}
+
+/// Add the options controlling how images are encoded to a command which writes one.
+fn output_args(cmd: App<'static, 'static>) -> App<'static, 'static> {
+  let formats: Vec<_> = Format::all().iter().map(|f| f.name()).collect();
+  let depths: Vec<_> = Depth::all().iter().map(|d| d.name()).collect();
+  cmd
+    .arg(Arg::with_name("format")
+      .long("format")
+      .help("Image format to write; by default it's picked from the output's extension, or PNG if there isn't one")
+      .possible_values(&formats)
+      .takes_value(true))
+    .arg(Arg::with_name("depth")
+      .long("depth")
+      .help("Bits per channel to write; 32f is 32-bit float. Defaults to 32f for float formats and 8 for the rest")
+      .possible_values(&depths)
+      .takes_value(true))
+    .arg(Arg::with_name("quality")
+      .long("quality")
+      .help("JPEG quality, from 1 to 100")
+      .validator(validate_quality)
+      .default_value("90"))
+    .arg(Arg::with_name("png-compression")
+      .long("png-compression")
+      .help("How hard to try to compress PNGs")
+      .possible_values(output::PNG_COMPRESSIONS)
+      .default_value(output::PNG_COMPRESSIONS[0]))
+    .arg(Arg::with_name("png-filter")
+      .long("png-filter")
+      .help("Filter to apply to PNG rows before compressing them")
+      .possible_values(output::PNG_FILTERS)
+      .default_value(output::PNG_FILTERS[0]))
+}
+
+/// An option's value from the command line if it was given there, or else the config, or else its default.
+fn option<'a>(args: &'a ArgMatches, config: &'a Config, name: &str) -> Option<&'a str> {
+  match (args.occurrences_of(name), config.option(name)) {
+    (0, Some(setting)) => Some(&setting.value),
+    _ => args.value_of(name),
+  }
+}
+
+/// Work out how to encode the output from the encoding options and the path it's being written to. The config's
+/// format is only used if the path's extension doesn't say.
+fn encoding(args: &ArgMatches, config: &Config, output_path: &str) -> Result<Encoding, String> {
+  // the unwraps are safe: these all have default or possible values checked by clap or the config
+  let format = match args.value_of("format") {
+    Some(name) => Some(Format::by_name(name).unwrap()),
+    None => Format::from_path(Path::new(output_path))
+      .or_else(|| option(args, config, "format").map(|name| Format::by_name(name).unwrap())),
+  };
+  // not every generator makes images, so an extension we don't know (like .txt) is only a problem for images
+  let guessed = format.is_none() && Path::new(output_path).extension().is_some();
+  let format = format.unwrap_or(Format::Png);
+  let encoding = Encoding {
+    format,
+    guessed,
+    depth: option(args, config, "depth").map_or(format.default_depth(), |name| Depth::by_name(name).unwrap()),
+    jpeg_quality: option(args, config, "quality").unwrap().parse().unwrap(),
+    png_compression: output::png_compression(option(args, config, "png-compression").unwrap()).unwrap(),
+    png_filter: output::png_filter(option(args, config, "png-filter").unwrap()).unwrap(),
+  };
+  encoding.check()?;
+  Ok(encoding)
+}
 
-fn mk_app() -> App<'static, 'static> {
+/// The option to stream output out as it's rendered, for commands which render a generator's output in full.
+fn stream_arg() -> Arg<'static, 'static> {
+  Arg::with_name("stream")
+    .long("stream")
+    .help("Render and write the image a band of rows at a time, to keep memory use down; PNG only")
+}
+
+/// Build the command line option for one of a generator's parameters. Required parameters aren't required by clap,
+/// since a sweep or the config can give them instead; resolving the parameters catches it if nothing does.
+fn param_arg(param: Param) -> Arg<'static, 'static> {
+  let mut arg = Arg::with_name(param.name)
+    .long(param.name)
+    .help(param.help);
+  if let Some(short) = param.short {
+    arg = arg.short(short.to_string());
+  }
+  // sizes are two values to clap, which it can't have a default for, so resolving the parameters fills theirs in
+  if let (Some(default), false) = (param.default, param.kind == Kind::Size) {
+    arg = arg.default_value(default);
+  }
+  match param.kind {
+    Kind::Flag => arg,
+    Kind::Size => arg.value_names(&["width", "height"]).validator(validate_dimension),
+    Kind::Enum(choices) => arg.takes_value(true).possible_values(choices),
+    _ => arg.takes_value(true).validator(move |s| param.parse(&s).map(|_| ())),
+  }
+}
+
+/// Pull the parameters actually given on the command line back out of a generator's options, as text ready to be
+/// resolved. Clap's defaults are left out, so they don't hide the config's.
+fn cli_params(schema: &[Param], args: &ArgMatches) -> Vec<(&'static str, String)> {
+  schema.iter().filter(|param| args.occurrences_of(param.name) > 0).filter_map(|param| match param.kind {
+    Kind::Flag => Some((param.name, true.to_string())),
+    Kind::Size => args.values_of(param.name).map(|vals| (param.name, vals.collect::<Vec<_>>().join("x"))),
+    _ => args.value_of(param.name).map(|val| (param.name, val.to_string())),
+  }).collect()
+}
+
+/// A generator's parameters from the config, overridden by any given on the command line.
+fn given_params(gen: &dyn gens::Gen, config: &Config, args: &ArgMatches) -> Vec<(String, String)> {
+  let generator = format!("{}/{}", gen.category().name(), gen.command());
+  let configured = config.params(&generator).into_iter()
+    .map(|(name, setting)| (name.to_string(), setting.value.clone()));
+  let cli = cli_params(&gen.params(), args).into_iter().map(|(name, value)| (name.to_string(), value));
+  configured.chain(cli).collect()
+}
+
+fn mk_app(registry: &Registry) -> App<'static, 'static> {
   let mut app = App::new("hart")
     .version("1")
     .author("Nic Hartley <the@redfennec.dev>")
@@ -24,6 +223,24 @@ fn mk_app() -> App<'static, 'static> {
     .setting(AppSettings::SubcommandRequiredElseHelp)
     .setting(AppSettings::VersionlessSubcommands)
     .setting(AppSettings::DisableHelpSubcommand)
+    .arg(Arg::with_name("quiet")
+      .short("q")
+      .long("quiet")
+      .help("Only print warnings and errors, and no progress bar")
+      .global(true))
+    .arg(Arg::with_name("verbose")
+      .short("v")
+      .long("verbose")
+      .help("Print more about what's going on; -vv for even more")
+      .multiple(true)
+      .conflicts_with("quiet")
+      .global(true))
+    .arg(Arg::with_name("preview")
+      .long("preview")
+      .help("Render at 1/N of the size, sampling the same points as the full render would, for a quick look")
+      .value_name("N")
+      .validator(validate_dimension)
+      .global(true))
     .subcommand(SubCommand::with_name("completions")
       .about("Generate shell completions")
       .arg(Arg::with_name("shell")
@@ -32,11 +249,209 @@ fn mk_app() -> App<'static, 'static> {
       .arg(Arg::with_name("output")
         .short("o")
         .long("output")
-        .help("The path to write the completion to")));
+        .help("The path to write the completion to")))
+    .subcommand(output_args(SubCommand::with_name("reproduce")
+      .about("Re-run the generator that made an image, with the same arguments and seed")
+      .arg(Arg::with_name("image")
+        .help("An image generated by hart, or - to read it from stdin")
+        .required(true))
+      .arg(Arg::with_name("output")
+        .short("o")
+        .long("output")
+        .help("Path to write the new output to, or - for stdout; defaults to the image's path with -reproduced added, \
+          or stdout if the image came from stdin")
+        .takes_value(true))
+      .arg(stream_arg())))
+    .subcommand(SubCommand::with_name("config")
+      .about("Look at the defaults set by config files and the environment")
+      .setting(AppSettings::SubcommandRequiredElseHelp)
+      .setting(AppSettings::VersionlessSubcommands)
+      .setting(AppSettings::DisableHelpSubcommand)
+      .subcommand(SubCommand::with_name("show")
+        .about("Print the value every option would have, and where it came from, and the same for the parameters of \
+          every generator the config sets any for")
+        .arg(Arg::with_name("generator")
+          .help("Show every parameter of just this generator, like basic/worley, instead"))))
+    .subcommand(SubCommand::with_name("list")
+      .about("List every generator, by category")
+      .arg(Arg::with_name("tag")
+        .long("tag")
+        .help("Only list generators with this tag")
+        .takes_value(true))
+      .arg(json_arg()))
+    .subcommand(SubCommand::with_name("describe")
+      .about("Describe a generator and every parameter it takes")
+      .arg(Arg::with_name("category")
+        .help("The generator's category, like basic")
+        .required(true))
+      .arg(Arg::with_name("generator")
+        .help("The generator's name or one of its aliases, like worley")
+        .required(true))
+      .arg(json_arg()))
+    .subcommand(SubCommand::with_name("run")
+      .about("Run every job in a job file, running independent jobs at the same time")
+      .arg(Arg::with_name("jobs")
+        .help("The job file, in TOML; see the docs for hart::jobs for what goes in it")
+        .required(true))
+      .arg(Arg::with_name("watch")
+        .long("watch")
+        .help("Keep running, and run the jobs again whenever the job file or any of their inputs change")))
+    .subcommand(SubCommand::with_name("sheet")
+      .about("Render thumbnails of many variants of a generator, and lay them out in a labelled grid")
+      .setting(AppSettings::SubcommandRequiredElseHelp)
+      .setting(AppSettings::VersionlessSubcommands)
+      .setting(AppSettings::DisableHelpSubcommand)
+      .subcommands(gen_commands(registry, |cmd| output_args(sheet_args(seed_args(cmd))))));
 
+  for category_cmd in gen_commands(registry, |cmd| output_args(render_args(seed_args(cmd)))) {
+    app = app.subcommand(category_cmd);
+  }
+
+  app
+}
+
+/// The option to write a description as JSON, for other programs to read; see the docs for hart::schema for what's
+/// in it.
+fn json_arg() -> Arg<'static, 'static> {
+  Arg::with_name("json")
+    .long("json")
+    .help("Write it as JSON, for other programs to read")
+}
+
+/// Add the options for picking the seed, or several seeds and parameter sweeps, to a generator's command.
+fn seed_args(cmd: App<'static, 'static>) -> App<'static, 'static> {
+  cmd
+    .arg(Arg::with_name("seed")
+      .long("seed")
+      .help("Text to seed the random generator with")
+      .takes_value(true))
+    .arg(Arg::with_name("seed-file")
+      .long("seed-file")
+      .help("Path to a file containing the seed")
+      .takes_value(true))
+    .arg(Arg::with_name("seed-hex")
+      .long("seed-hex")
+      .help("Seed bytes, written in hexadecimal")
+      .validator(validate_hex)
+      .takes_value(true))
+    .arg(Arg::with_name("seed-b64")
+      .long("seed-b64")
+      .help("Seed bytes, written in base64")
+      .validator(validate_b64)
+      .takes_value(true))
+    .arg(Arg::with_name("seed-int")
+      .long("seed-int")
+      .help("A nonnegative integer to seed the random generator with")
+      .validator(validate_int)
+      .takes_value(true))
+    .arg(Arg::with_name("random-seed")
+      .long("random-seed")
+      .help("Pick a random seed, and print it so it can be reused"))
+    .arg(Arg::with_name("seed-stdin")
+      .long("seed-stdin")
+      .help("Read the seed from stdin without prompting, minus one trailing newline"))
+    .arg(Arg::with_name("seeds")
+      .long("seeds")
+      .help("Render once for each of these text seeds")
+      .value_name("a,b,c")
+      .takes_value(true))
+    .arg(Arg::with_name("seed-range")
+      .long("seed-range")
+      .help("Render once for each integer seed in a range, like 0..100 (which leaves out 100) or 1..=6 (which \
+        doesn't leave out 6)")
+      .value_name("range")
+      .validator(|s| batch::seed_range(&s).map(|_| ())))
+    .group(ArgGroup::with_name("seed-source")
+      .args(&[
+        "seed", "seed-file", "seed-hex", "seed-b64", "seed-int", "random-seed", "seed-stdin", "seeds", "seed-range",
+      ]))
+    .arg(Arg::with_name("sweep")
+      .long("sweep")
+      .help("Render once for each value of a parameter, like octaves=1..=6 or direction=radial,conic; give it more \
+        than once to render every combination")
+      .value_name("param=values")
+      .multiple(true)
+      .number_of_values(1)
+      .validator(|s| batch::Sweep::parse(&s).map(|_| ())))
+}
+
+/// Add the options for where and how to render a generator's output to its command.
+fn render_args(cmd: App<'static, 'static>) -> App<'static, 'static> {
+  cmd
+    .arg(Arg::with_name("output")
+      .short("o")
+      .long("output")
+      .help("Path to write the output to, or - for stdout; the image format is picked from the extension unless \
+        --format is given. When rendering several variants, {seed} and {<param>} are filled in, as in \
+        out-{seed}-{octaves}.png")
+      .required_unless("term")
+      .takes_value(true))
+    .arg(Arg::with_name("region")
+      .long("region")
+      .help("Only render this part of the canvas, exactly as it'd look cropped out of the whole thing")
+      .value_name("x,y,w,h")
+      .validator(|s| parse_region(&s).map(|_| ()))
+      .conflicts_with("tiles"))
+    .arg(Arg::with_name("canvas")
+      .long("canvas")
+      .help("The size of the whole virtual canvas, which --region has to fit in; for --tiles, it defaults to the \
+        generator's own size")
+      .value_name("W,H")
+      .validator(|s| parse_canvas(&s).map(|_| ())))
+    .arg(Arg::with_name("tiles")
+      .long("tiles")
+      .help("Write a slippy map pyramid of tiles, from zoom 0 to this, as <output>/zoom/x/y.png")
+      .value_name("max zoom")
+      .validator(validate_zoom)
+      .conflicts_with_all(&["stream", "seeds", "seed-range", "sweep"]))
+    .arg(stream_arg())
+    .arg(Arg::with_name("term")
+      .long("term")
+      .help("Show the output in the terminal, in 24-bit color, instead of writing it; --preview helps it fit")
+      .conflicts_with_all(&["output", "tiles", "seeds", "seed-range", "sweep", "stream"]))
+    .arg(Arg::with_name("watch")
+      .long("watch")
+      .help("Keep running, and render again whenever an input, the seed file, or a config file changes"))
+    .arg(Arg::with_name("watch-preview")
+      .long("watch-preview")
+      .help("With --watch, render a quick preview at 1/N of the size before each full render, to the same output")
+      .value_name("N")
+      .validator(validate_dimension)
+      .requires("watch")
+      .conflicts_with_all(&["tiles", "seeds", "seed-range", "sweep"]))
+}
+
+/// Add the options for laying out a contact sheet to a generator's command.
+fn sheet_args(cmd: App<'static, 'static>) -> App<'static, 'static> {
+  cmd
+    .arg(Arg::with_name("output")
+      .short("o")
+      .long("output")
+      .help("Path to write the sheet to, or - for stdout; the image format is picked from the extension unless \
+        --format is given")
+      .required(true)
+      .takes_value(true))
+    .arg(Arg::with_name("thumbnail")
+      .long("thumbnail")
+      .help("How many pixels each thumbnail can be across or down")
+      .value_name("pixels")
+      .validator(validate_dimension)
+      .default_value("160"))
+    .arg(Arg::with_name("columns")
+      .long("columns")
+      .help("How many thumbnails to fit across the sheet; by default, enough to make it about square")
+      .value_name("count")
+      .validator(validate_dimension))
+}
+
+/// Build a command for each category with generators in it, and a command under that for each of its generators,
+/// with `args` adding whatever options should go alongside the generator's own parameters.
+fn gen_commands(registry: &Registry, args: impl Fn(App<'static, 'static>) -> App<'static, 'static>)
+  -> Vec<App<'static, 'static>>
+{
   let mut category_cmds = HashMap::new();
-  for category in gens::Category::all().iter() {
-    category_cmds.insert(*category,
+  for category in registry.categories() {
+    category_cmds.insert(category,
       SubCommand::with_name(category.name())
         .about(category.description())
         .setting(AppSettings::SubcommandRequiredElseHelp)
@@ -45,78 +460,396 @@ fn mk_app() -> App<'static, 'static> {
       );
   }
 
-  for gen in &gens::Gen::all() {
-    let gen_cmd = SubCommand::with_name(gen.command())
+  for gen in registry.all() {
+    let mut gen_cmd = SubCommand::with_name(gen.command())
       .about(gen.about())
-      .arg(Arg::with_name("seed")
-        .long("seed")
-        .help("Data to seed the random generator with")
-        .takes_value(true))
-      .arg(Arg::with_name("seed-file")
-        .long("seed-file")
-        .help("Path to a file containing the seed")
-        .conflicts_with("seed")
-        .takes_value(true))
-      .arg(Arg::with_name("output")
-        .short("o")
-        .long("output")
-        .help("Path to write the output to; extension automatically appended")
-        .required(true)
-        .takes_value(true));
-    let added = gen.setup_cmd(gen_cmd);
+      .aliases(gen.aliases());
+    if gen.hidden() {
+      gen_cmd = gen_cmd.setting(AppSettings::Hidden);
+    }
+    if gen.experimental() {
+      gen_cmd = gen_cmd.after_help(schema::EXPERIMENTAL);
+    }
+    let added = gen.params().into_iter().fold(args(gen_cmd), |cmd, param| cmd.arg(param_arg(param)));
 
     let cat_cmd = category_cmds.remove(&gen.category()).unwrap();
     let with_gen = cat_cmd.subcommand(added);
     category_cmds.insert(gen.category(), with_gen);
   }
 
-  for (_, subcmd) in category_cmds.into_iter() {
-    app = app.subcommand(subcmd);
+  registry.categories().into_iter().map(|category| category_cmds.remove(&category).unwrap()).collect()
+}
+
+/// Figure out the seed from whichever seed argument was passed.
+/// If there weren't any, it's read from stdin, with a prompt if stdin is a terminal.
+fn read_seed(args: &ArgMatches) -> Result<Vec<u8>, Failure> {
+  // the unwraps on decoding are safe: the arguments have already been validated by clap
+  if let Some(data) = args.value_of("seed") {
+    Ok(data.as_bytes().to_vec())
+  } else if let Some(path) = args.value_of("seed-file") {
+    fs::read(path).or_exit(Exit::NoInput, || format!("couldn't read the seed from {}", path))
+  } else if let Some(hex) = args.value_of("seed-hex") {
+    Ok(seed::from_hex(hex).unwrap())
+  } else if let Some(b64) = args.value_of("seed-b64") {
+    Ok(seed::from_b64(b64).unwrap())
+  } else if let Some(int) = args.value_of("seed-int") {
+    Ok(seed::from_int(int).unwrap())
+  } else if args.is_present("random-seed") {
+    let data = seed::random();
+    // not logged, since -q would hide it, and it's the only way to get the seed back for anything without a recipe
+    eprintln!("Using random seed: --seed-hex {}", seed::to_hex(&data));
+    Ok(data)
+  } else {
+    let stdin = io::stdin();
+    let data = if !args.is_present("seed-stdin") && stdin.is_terminal() {
+      // someone's typing it, so it ends when they press enter
+      eprintln!("Enter some text as a seed:");
+      let mut line = String::new();
+      stdin.read_line(&mut line).or_exit(Exit::Io, || "couldn't read the seed from stdin")?;
+      line.into_bytes()
+    } else {
+      let mut data = Vec::new();
+      stdin.lock().read_to_end(&mut data).or_exit(Exit::Io, || "couldn't read the seed from stdin")?;
+      data
+    };
+    Ok(seed::trim_newline(data))
   }
+}
 
-  app
+/// How much smaller `--preview` asked for everything to be rendered, if it did.
+fn preview_factor(args: &ArgMatches) -> Option<u32> {
+  // the unwrap is safe: it's already been validated by clap
+  args.value_of("preview").map(|factor| factor.parse().unwrap())
 }
 
-fn main() {
-  let matches = mk_app().get_matches();
-
-  let (category, catargs) = matches.subcommand();
-  let catargs = catargs.expect("How??");
-
-  if category == "completions" {
-    // neither unwrap will panic: --shell has a default value and the only valid options are Shell::variants()
-    let shell = Shell::from_str(catargs.value_of("shell").unwrap()).unwrap();
-    match catargs.value_of("output") {
-      Some(path) => {
-        let file = fs::File::create(path).expect("Failed to open output");
-        mk_app().gen_completions_to("hart", shell, &mut BufWriter::new(file));
-      }
-      None => {
-        mk_app().gen_completions_to("hart", shell, &mut io::stdout())
-      }
+fn reproduce(registry: &Registry, config: &Config, args: &ArgMatches) -> Result<(), Failure> {
+  let image_path = args.value_of("image").unwrap();
+  let output_path = match args.value_of("output") {
+    Some(path) => path.to_string(),
+    None if image_path == STDIO => STDIO.to_string(),
+    None => run::reproduced_path(image_path),
+  };
+  let encoding = encoding(args, config, &output_path).map_err(Failure::usage)?;
+  run::reproduce(registry, image_path, &output_path, &encoding, preview_factor(args), args.is_present("stream"))
+}
+
+fn completions(registry: &Registry, args: &ArgMatches) -> Result<(), Failure> {
+  // neither unwrap will panic: --shell has a default value and the only valid options are Shell::variants()
+  let shell = Shell::from_str(args.value_of("shell").unwrap()).unwrap();
+  let mut sink = Sink::create(Path::new(args.value_of("output").unwrap_or(STDIO)))?;
+  mk_app(registry).gen_completions_to("hart", shell, &mut sink);
+  sink.finish()
+}
+
+/// How `{seed}` is written in output names when there's only the one seed.
+fn seed_label(args: &ArgMatches, seed: Vec<u8>) -> batch::Seed {
+  // the unwrap is safe: it's already been validated by clap
+  if let Some(text) = args.value_of("seed") {
+    batch::Seed::text(text)
+  } else if let Some(int) = args.value_of("seed-int") {
+    batch::Seed::int(int.parse().unwrap())
+  } else {
+    batch::Seed::bytes(seed)
+  }
+}
+
+/// Every combination of the seeds and sweeps given on the command line, with the generator's parameters resolved for
+/// each. All of them are checked before any seed is read.
+fn variants(gen: &dyn gens::Gen, config: &Config, args: &ArgMatches) -> Result<Vec<Variant>, Failure> {
+  let schema = gen.params();
+  let given = given_params(gen, config, args);
+  // the unwraps are safe: they've already been validated by clap
+  let sweeps: Vec<_> = args.values_of("sweep").into_iter().flatten()
+    .map(|sweep| batch::Sweep::parse(sweep).unwrap())
+    .collect();
+  for (i, sweep) in sweeps.iter().enumerate() {
+    if sweeps[..i].iter().any(|other| other.name == sweep.name) {
+      return Err(Failure::usage(format!("{} is swept more than once", sweep.name)));
     }
+  }
+  let seed_from_stdin = args.is_present("seed-stdin") || !args.is_present("seed-source");
+  let mut resolved = vec![];
+  for combination in batch::combinations(&sweeps) {
+    let swept = combination.iter().map(|(name, value)| (name.as_str(), value.as_str()));
+    let given = given.iter().map(|(name, value)| (name.as_str(), value.as_str()));
+    let params = params::resolve(&schema, given.chain(swept)).map_err(Failure::usage)?;
+    run::check_stdin(&schema, &params, seed_from_stdin).map_err(Failure::usage)?;
+    resolved.push((combination, params));
+  }
+  let seeds = if let Some(texts) = args.value_of("seeds") {
+    texts.split(',').map(batch::Seed::text).collect()
+  } else if let Some(range) = args.value_of("seed-range") {
+    batch::seed_range(range).unwrap()
   } else {
-    let (gen, genargs) = catargs.subcommand();
-    let genargs = genargs.expect("How????");
-
-    if let Some(gen) = gens::Gen::by_cli(category, gen) {
-      let seed = if let Some(data) = genargs.value_of("seed") {
-        data.as_bytes().to_vec()
-      } else if let Some(path) = genargs.value_of("seed-file") {
-        fs::read(path).expect("Failed to open seed file")
-      } else {
-        println!("Enter some text as a seed:");
-        let mut line = String::new();
-        io::stdin().read_line(&mut line).expect("Failed to read input");
-        line.as_bytes().to_vec()
-      };
-
-      let output_path = genargs.value_of("output").unwrap();
-      let mut output = BufWriter::new(fs::File::create(output_path).expect("Failed to open output"));
-
-      gen.run(genargs, &seed, &mut output).expect("Failed to generate");
-    } else {
-      panic!("Invalid subcommand??");
+    vec![seed_label(args, read_seed(args)?)]
+  };
+  Ok(seeds.iter()
+    .flat_map(|seed| resolved.iter().map(move |(swept, params)| {
+      Variant { seed: seed.clone(), swept: swept.clone(), params: params.clone() }
+    }))
+    .collect())
+}
+
+/// Render every combination of the seeds and sweeps given on the command line, in parallel, each to its own output
+/// named by filling in the `--output` template. Every variant is checked before any are rendered.
+fn batch(gen: &dyn gens::Gen, generator: String, config: &Config, args: &ArgMatches) -> Result<(), Failure> {
+  let template = args.value_of("output").unwrap();
+  let view = args.value_of("region").map(|region| View::region(parse_region(region).unwrap()));
+  let mut renders = vec![];
+  for Variant { seed, params, .. } in variants(gen, config, args)? {
+    let text = params.to_text();
+    let names = text.iter().map(|(name, value)| (name.as_str(), value.as_str()));
+    let names: Vec<_> = std::iter::once(("seed", seed.label.as_str())).chain(names).collect();
+    let output_path = batch::fill(template, &names).map_err(Failure::usage)?;
+    let recipe = Recipe {
+      version: VERSION.to_string(),
+      generator: generator.clone(),
+      params: text,
+      seed: seed.bytes,
+      view,
+    };
+    renders.push(run::Render {
+      recipe: match preview_factor(args) {
+        Some(factor) => run::preview(gen, &params, recipe, factor)?,
+        None => recipe,
+      },
+      params,
+      encoding: encoding(args, config, &output_path).map_err(Failure::usage)?,
+      output_path,
+    });
+  }
+  let canvas = args.value_of("canvas").map(|canvas| parse_canvas(canvas).unwrap());
+  run::batch(gen, renders, canvas, args.is_present("stream"))
+}
+
+/// Run every job in a job file, once or whenever it or the jobs' inputs change.
+fn run_jobs(registry: &Registry, args: &ArgMatches) -> Result<(), Failure> {
+  let path = args.value_of("jobs").unwrap();
+  let factor = preview_factor(args);
+  if !args.is_present("watch") {
+    return run::job_file(registry, path, factor);
+  }
+  run::watch(|| run::job_file_inputs(registry, path), || run::job_file(registry, path, factor))
+}
+
+/// The files a generator's output depends on, to watch for changes: the config files, the seed file if there is one,
+/// and any inputs, except for whatever the output is being written to.
+fn watched_files(gen: &dyn gens::Gen, registry: &Registry, args: &ArgMatches) -> Vec<PathBuf> {
+  let config = Config::load(registry).unwrap_or_default();
+  let schema = gen.params();
+  let is_path = |name: &str| schema.iter().any(|param| param.name == name && param.kind == Kind::Path);
+  // the unwraps are safe: they've already been validated by clap
+  let swept = args.values_of("sweep").into_iter().flatten()
+    .map(|sweep| batch::Sweep::parse(sweep).unwrap())
+    .filter(|sweep| is_path(&sweep.name))
+    .flat_map(|sweep| sweep.values);
+  let inputs = given_params(gen, &config, args).into_iter()
+    .filter(|(name, _)| is_path(name))
+    .map(|(_, value)| value)
+    .chain(swept)
+    .filter(|path| path != STDIO && Some(path.as_str()) != args.value_of("output"));
+  // a project config that doesn't exist yet might be made in the current directory
+  let local = config::local_file().or_else(|| Some(PathBuf::from(config::LOCAL_NAME)));
+  config::user_file().into_iter()
+    .chain(local)
+    .chain(args.value_of("seed-file").map(PathBuf::from))
+    .chain(inputs.map(PathBuf::from))
+    .collect()
+}
+
+/// Look up the generator a category's command asked for, along with its arguments and its full name.
+fn find_gen<'a>(registry: &Registry, category: &str, args: &'a ArgMatches<'a>)
+  -> Result<(&'static dyn gens::Gen, &'a ArgMatches<'a>, String), Failure>
+{
+  let (command, genargs) = args.subcommand();
+  let genargs = genargs.ok_or_else(|| Failure::usage(format!("no generator given for {}", category)))?;
+  let gen = registry.find(category, command)
+    .ok_or_else(|| Failure::usage(format!("there's no generator called {}/{}", category, command)))?;
+  if gen.experimental() {
+    log::warn!("{}/{} is experimental, so its output may change between versions", category, gen.command());
+  }
+  Ok((gen, genargs, format!("{}/{}", category, gen.command())))
+}
+
+/// Render a thumbnail of every variant asked for, and lay them out in a captioned grid.
+fn sheet(registry: &Registry, config: &Config, args: &ArgMatches) -> Result<(), Failure> {
+  let (category, catargs) = args.subcommand();
+  let catargs = catargs.ok_or_else(|| Failure::usage("no generator given for the sheet".to_string()))?;
+  let (gen, genargs, _) = find_gen(registry, category, catargs)?;
+  let variants = variants(gen, config, genargs)?;
+  // the unwraps are safe: they've already been validated by clap
+  let size = genargs.value_of("thumbnail").unwrap().parse().unwrap();
+  let columns = genargs.value_of("columns").map(|columns| columns.parse().unwrap());
+  let output_path = genargs.value_of("output").unwrap();
+  let encoding = encoding(genargs, config, output_path).map_err(Failure::usage)?;
+  run::sheet(gen, variants, size, columns, &encoding, output_path)
+}
+
+/// Run a generator as the command line says to, once or whenever anything it depends on changes.
+fn run_gen(registry: &Registry, config: &Config, category: &str, args: &ArgMatches) -> Result<(), Failure> {
+  let (gen, genargs, generator) = find_gen(registry, category, args)?;
+  if !genargs.is_present("watch") {
+    return render_gen(gen, genargs, generator, config, None);
+  }
+  if genargs.is_present("seed-stdin") || !genargs.is_present("seed-source") {
+    return Err(Failure::usage("stdin can only be read once, so --watch needs the seed some other way".to_string()));
+  }
+  // the unwrap is safe: it's already been validated by clap
+  let watch_preview = genargs.value_of("watch-preview").map(|factor| factor.parse().unwrap());
+  run::watch(|| watched_files(gen, registry, genargs), || {
+    // the config might be what changed
+    let config = Config::load(registry)
+      .map_err(|e| Failure::from(GenFail::Data(format!("the config is broken: {}", e))))?;
+    render_gen(gen, genargs, generator.clone(), &config, watch_preview)
+  })
+}
+
+/// Render a generator's output, or a batch of variants of it. If `watch_preview` is given, a preview at
+/// 1/`watch_preview` of the size is rendered first.
+fn render_gen(
+  gen: &dyn gens::Gen, genargs: &ArgMatches, generator: String, config: &Config, watch_preview: Option<u32>,
+) -> Result<(), Failure> {
+  if ["seeds", "seed-range", "sweep"].iter().any(|arg| genargs.is_present(arg)) {
+    return run::with_progress(|| batch(gen, generator, config, genargs));
+  }
+
+  let params = params::resolve(&gen.params(), given_params(gen, config, genargs)).map_err(Failure::usage)?;
+  let seed_from_stdin = genargs.is_present("seed-stdin") || !genargs.is_present("seed-source");
+  run::check_stdin(&gen.params(), &params, seed_from_stdin).map_err(Failure::usage)?;
+  let seed = read_seed(genargs)?;
+  let recipe = Recipe {
+    version: VERSION.to_string(),
+    generator,
+    params: params.to_text(),
+    seed,
+    // the unwrap is safe: the region has already been validated by clap
+    view: genargs.value_of("region").map(|region| View::region(parse_region(region).unwrap())),
+  };
+  let canvas = genargs.value_of("canvas").map(|canvas| parse_canvas(canvas).unwrap());
+
+  if let Some(zoom) = genargs.value_of("tiles") {
+    if preview_factor(genargs).is_some() {
+      let size = run::TILE_SIZE;
+      return Err(Failure::usage(format!("tiles are always {} pixels across, so they can't be previewed", size)));
     }
+    let output_path = genargs.value_of("output").unwrap();
+    let encoding = encoding(genargs, config, output_path).map_err(Failure::usage)?;
+    let zoom = zoom.parse().unwrap();
+    return run::with_progress(|| run::tiles(gen, &params, &recipe, &encoding, canvas, zoom, output_path));
+  }
+  let recipe = match preview_factor(genargs) {
+    Some(factor) => run::preview(gen, &params, recipe, factor)?,
+    None => recipe,
+  };
+  let render = |recipe: &Recipe| match genargs.value_of("output") {
+    // there's only no output with --term
+    None => run::show(gen, &params, recipe),
+    Some(output_path) => {
+      let encoding = encoding(genargs, config, output_path).map_err(Failure::usage)?;
+      let stream = genargs.is_present("stream");
+      run::generate(gen, &params, recipe, &encoding, canvas, stream, output_path)
+    }
+  };
+  if let Some(factor) = watch_preview {
+    run::with_progress(|| render(&run::preview(gen, &params, recipe.clone(), factor)?))?;
+  }
+  run::with_progress(|| render(&recipe))
+}
+
+/// Every generator that isn't hidden, in every category, optionally only those with a certain tag.
+fn list(registry: &Registry, args: &ArgMatches) -> Result<(), Failure> {
+  let gens: Vec<_> = match args.value_of("tag") {
+    Some(tag) => registry.tagged(tag).filter(|gen| !gen.hidden()).collect(),
+    None => registry.visible().collect(),
+  };
+  if args.is_present("json") {
+    return run::print(&[schema::list(registry, &gens)]);
+  }
+  run::print(&schema::list_text(registry, &gens))
+}
+
+/// Describe one generator and its parameters, even if it's hidden.
+fn describe_gen(registry: &Registry, args: &ArgMatches) -> Result<(), Failure> {
+  // the unwraps are safe: they're required by clap
+  let (category, command) = (args.value_of("category").unwrap(), args.value_of("generator").unwrap());
+  let gen = registry.find(category, command)
+    .ok_or_else(|| Failure::usage(format!("there's no generator called {}/{}", category, command)))?;
+  if args.is_present("json") {
+    return run::print(&[schema::describe(gen)]);
+  }
+  run::print(&schema::describe_text(gen))
+}
+
+/// Print the effective value of every option, and of the parameters of every configured generator (or just the one
+/// asked for), along with where each came from.
+fn show_config(registry: &Registry, config: &Config, args: &ArgMatches) -> Result<(), Failure> {
+  let find = |generator: &str| {
+    generator.split_once('/').and_then(|(category, command)| registry.find(category, command))
+  };
+  let gens: Vec<_> = match args.value_of("generator") {
+    Some(generator) => {
+      vec![find(generator).ok_or_else(|| Failure::usage(format!("there's no generator called {}", generator)))?]
+    }
+    None => config.generators().into_iter().filter_map(find).collect(),
+  };
+
+  for (generator, source) in &config.unknown {
+    log::warn!("{} sets parameters for {}, but there's no generator by that name", source, generator);
+  }
+  run::print(&config.show(&gens))
+}
+
+fn main() {
+  let mut registry = Registry::builtin();
+  // plugins have to be loaded before the arguments can be parsed, since they add commands, so the failures are only
+  // reported once logging is set up
+  let plugin_failures = match plugin::default_dir() {
+    // safe as long as the user trusts their own plugins directory, which is all any plugin system can promise
+    Some(dir) => unsafe { plugin::load_dir(&mut registry, &dir) },
+    None => vec![],
+  };
+  let matches = mk_app(&registry).get_matches_safe().unwrap_or_else(|e| match e.kind {
+    clap::ErrorKind::HelpDisplayed | clap::ErrorKind::VersionDisplayed => e.exit(),
+    _ => {
+      eprintln!("{}", e.message);
+      process::exit(Exit::Usage as i32);
+    }
+  });
+  // the config can say how much to log, so it's read before logging is set up, and a broken one reported after
+  let (config, broken_config) = match Config::load(&registry) {
+    Ok(config) => (config, None),
+    Err(e) => (Config::new(), Some(e)),
+  };
+  init_logging(&matches, &config);
+  for (path, failure) in plugin_failures {
+    log::warn!("couldn't load the plugin {}: {}", path.display(), failure);
+  }
+  if let Some(e) = broken_config {
+    log::error!("the config is broken: {}", e);
+    process::exit(Exit::Data as i32);
+  }
+  for (generator, source) in &config.unknown {
+    // it might be from a plugin that's only sometimes installed, so it's only worth a warning in `config show`
+    log::debug!("{} sets parameters for {}, but there's no generator by that name", source, generator);
+  }
+
+  let result = match matches.subcommand() {
+    ("completions", Some(args)) => completions(&registry, args),
+    ("config", Some(args)) => match args.subcommand() {
+      ("show", Some(args)) => show_config(&registry, &config, args),
+      _ => Err(Failure::usage("no config command given".to_string())),
+    },
+    ("list", Some(args)) => list(&registry, args),
+    ("describe", Some(args)) => describe_gen(&registry, args),
+    ("reproduce", Some(args)) => reproduce(&registry, &config, args),
+    ("sheet", Some(args)) => sheet(&registry, &config, args),
+    ("run", Some(args)) => run_jobs(&registry, args),
+    (category, Some(args)) => run_gen(&registry, &config, category, args),
+    (_, None) => Err(Failure::usage("no command given".to_string())),
+  };
+
+  if let Err(failure) = result {
+    log::error!("{}", run::describe(&failure.error));
+    process::exit(failure.exit as i32);
   }
 }
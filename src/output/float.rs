@@ -0,0 +1,154 @@
+//! Writers for the float formats the image crate doesn't have encoders for.
+//!
+//! Both take flat, row-major subpixels with 1 (gray), 3 (RGB), or 4 (RGBA) channels, the way `Image` stores them.
+
+use std::io::{self, Write};
+
+/// Turn gray or RGBA subpixels into RGB, for formats which only do color.
+pub fn rgb(data: &[f32], channels: usize) -> Vec<f32> {
+  match channels {
+    1 => data.iter().flat_map(|&v| vec![v, v, v]).collect(),
+    3 => data.to_vec(),
+    _ => data.chunks(channels).flat_map(|px| px[..3].to_vec()).collect(),
+  }
+}
+
+/// Write a portable float map: gray if the image is, RGB otherwise, with any alpha dropped.
+pub fn write_pfm(width: u32, height: u32, channels: usize, data: &[f32], output: &mut dyn Write) -> io::Result<()> {
+  let (magic, data, channels) = match channels {
+    1 => ("Pf", data.to_vec(), 1),
+    _ => ("PF", rgb(data, channels), 3),
+  };
+  // a negative scale means little-endian
+  write!(output, "{}\n{} {}\n-1.0\n", magic, width, height)?;
+  // rows go from the bottom up
+  let row_len = width as usize * channels;
+  for row in data.chunks(row_len).rev() {
+    let bytes: Vec<u8> = row.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect();
+    output.write_all(&bytes)?;
+  }
+  Ok(())
+}
+
+/// One attribute of an OpenEXR header: name, type name, and value.
+fn exr_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+  header.extend_from_slice(name.as_bytes());
+  header.push(0);
+  header.extend_from_slice(kind.as_bytes());
+  header.push(0);
+  header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+  header.extend_from_slice(value);
+}
+
+/// Write a single-part, scanline, uncompressed OpenEXR file with 32-bit float channels.
+/// Gray images get a single luminance channel, `Y`; color ones get `R`, `G`, `B`, and `A` if they have it.
+pub fn write_exr(width: u32, height: u32, channels: usize, data: &[f32], output: &mut dyn Write) -> io::Result<()> {
+  // channels have to be listed, and stored, in alphabetical order; these are the indices into each pixel
+  let names: &[(&str, usize)] = match channels {
+    1 => &[("Y", 0)],
+    3 => &[("B", 2), ("G", 1), ("R", 0)],
+    _ => &[("A", 3), ("B", 2), ("G", 1), ("R", 0)],
+  };
+
+  let mut header = Vec::new();
+  header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]);
+  header.extend_from_slice(&2u32.to_le_bytes());
+
+  let mut chlist = Vec::new();
+  for (name, _) in names {
+    chlist.extend_from_slice(name.as_bytes());
+    chlist.push(0);
+    chlist.extend_from_slice(&2i32.to_le_bytes()); // FLOAT
+    chlist.extend_from_slice(&[0, 0, 0, 0]); // pLinear, then reserved
+    chlist.extend_from_slice(&1i32.to_le_bytes()); // x sampling
+    chlist.extend_from_slice(&1i32.to_le_bytes()); // y sampling
+  }
+  chlist.push(0);
+  let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1].iter().flat_map(|v| v.to_le_bytes().to_vec()).collect();
+
+  exr_attribute(&mut header, "channels", "chlist", &chlist);
+  exr_attribute(&mut header, "compression", "compression", &[0]);
+  exr_attribute(&mut header, "dataWindow", "box2i", &window);
+  exr_attribute(&mut header, "displayWindow", "box2i", &window);
+  exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+  exr_attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+  exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+  exr_attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+  header.push(0);
+
+  // uncompressed files have one scanline per chunk, each prefixed with its y and size, and a table of where they are
+  let row_len = width as usize * channels;
+  let chunk_size = 8 + row_len * 4;
+  let table_end = header.len() + height as usize * 8;
+  for y in 0..height as usize {
+    header.extend_from_slice(&((table_end + y * chunk_size) as u64).to_le_bytes());
+  }
+  output.write_all(&header)?;
+
+  let mut chunk = Vec::with_capacity(chunk_size);
+  for (y, row) in data.chunks(row_len).enumerate() {
+    chunk.clear();
+    chunk.extend_from_slice(&(y as i32).to_le_bytes());
+    chunk.extend_from_slice(&((row_len * 4) as i32).to_le_bytes());
+    for &(_, idx) in names {
+      for px in row.chunks(channels) {
+        chunk.extend_from_slice(&px[idx].to_le_bytes());
+      }
+    }
+    output.write_all(&chunk)?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    std::convert::TryInto as _,
+  };
+
+  fn le_f32(bytes: &[u8]) -> f32 {
+    f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+  }
+
+  #[test]
+  fn pfm_is_bottom_up() {
+    let mut out = Vec::new();
+    write_pfm(2, 2, 1, &[0.0, 0.25, 0.5, 1.0], &mut out).unwrap();
+    let header = b"Pf\n2 2\n-1.0\n";
+    assert_eq!(&out[..header.len()], header);
+    let values: Vec<f32> = out[header.len()..].chunks(4).map(le_f32).collect();
+    assert_eq!(values, vec![0.5, 1.0, 0.0, 0.25]);
+  }
+
+  #[test]
+  fn pfm_drops_alpha() {
+    let mut out = Vec::new();
+    write_pfm(1, 1, 4, &[0.1, 0.2, 0.3, 0.4], &mut out).unwrap();
+    assert!(out.starts_with(b"PF\n1 1\n"));
+    assert_eq!(out.len(), b"PF\n1 1\n-1.0\n".len() + 12);
+  }
+
+  #[test]
+  fn exr_chunks_are_where_the_table_says() {
+    let data = [0.0, 0.1, 0.2, 1.0, 1.1, 1.2, 2.0, 2.1, 2.2, 3.0, 3.1, 3.2];
+    let mut out = Vec::new();
+    write_exr(2, 2, 3, &data, &mut out).unwrap();
+    assert_eq!(&out[..4], &[0x76, 0x2f, 0x31, 0x01]);
+
+    // the offset table sits right after the header's terminating null, which follows the last attribute
+    let last = b"screenWindowWidth\0float\0";
+    let attr = out.windows(last.len()).position(|w| w == last).unwrap();
+    let table = attr + last.len() + 4 + 4 + 1;
+    for y in 0..2 {
+      let offset = u64::from_le_bytes(out[table + y * 8..table + y * 8 + 8].try_into().unwrap()) as usize;
+      assert_eq!(i32::from_le_bytes(out[offset..offset + 4].try_into().unwrap()), y as i32);
+      assert_eq!(i32::from_le_bytes(out[offset + 4..offset + 8].try_into().unwrap()), 24);
+      // B, G, R planes, each a row long
+      let values: Vec<f32> = out[offset + 8..offset + 32].chunks(4).map(le_f32).collect();
+      let row = &data[y * 6..y * 6 + 6];
+      assert_eq!(values, vec![row[2], row[5], row[1], row[4], row[0], row[3]]);
+    }
+    assert_eq!(out.len(), table + 16 + 2 * 32);
+  }
+}
@@ -0,0 +1,751 @@
+//! What generators produce, and the shared stage that turns it into bytes on disk.
+//!
+//! Generators hand back an [`Output`] rather than encoding anything themselves, so everything that happens after
+//! generation (picking a format, embedding the recipe, and so on) works the same way for every generator.
+
+use {
+  crate::{
+    gens::{GenFail, Result},
+    recipe::Recipe,
+    utils::{
+      render::{Progress, Raster, Renderer, Tile, View},
+      Span,
+    },
+  },
+  image::{
+    codecs::{
+      bmp::BmpEncoder,
+      farbfeld::FarbfeldEncoder,
+      gif::GifEncoder,
+      hdr::HdrEncoder,
+      ico::IcoEncoder,
+      jpeg::JpegEncoder,
+      png::{CompressionType, FilterType, PngEncoder},
+      pnm::PnmEncoder,
+      tga::TgaEncoder,
+      tiff::TiffEncoder,
+    },
+    ColorType, DynamicImage, GrayImage, ImageBuffer, Luma, Pixel, Primitive, Rgb, RgbImage, Rgba, RgbaImage,
+  },
+  std::{
+    io::{Cursor, Write},
+    path::Path,
+    thread,
+  },
+};
+
+mod float;
+mod png;
+mod term;
+pub use {
+  png::PngStream,
+  term::{half_blocks, terminal},
+};
+
+pub type Gray16Image = ImageBuffer<Luma<u16>, Vec<u16>>;
+pub type Rgb16Image = ImageBuffer<Rgb<u16>, Vec<u16>>;
+pub type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
+pub type GrayF32Image = ImageBuffer<Luma<f32>, Vec<f32>>;
+pub type RgbF32Image = ImageBuffer<Rgb<f32>, Vec<f32>>;
+pub type RgbaF32Image = ImageBuffer<Rgba<f32>, Vec<f32>>;
+
+/// An in-memory image, in whatever pixel format the generator worked in.
+/// Float images are nominally in the range 0 to 1, but nothing enforces that.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Image {
+  Luma8(GrayImage),
+  Rgb8(RgbImage),
+  Rgba8(RgbaImage),
+  Luma16(Gray16Image),
+  Rgb16(Rgb16Image),
+  Rgba16(Rgba16Image),
+  LumaF32(GrayF32Image),
+  RgbF32(RgbF32Image),
+  RgbaF32(RgbaF32Image),
+}
+
+/// Flatten a buffer's subpixels into big-endian bytes, the way PNG wants them.
+fn be_bytes<P: Pixel<Subpixel = u16> + 'static>(img: &ImageBuffer<P, Vec<u16>>) -> Vec<u8> {
+  img.as_raw().iter().flat_map(|v| v.to_be_bytes().to_vec()).collect()
+}
+
+fn raw_bytes<P: Pixel<Subpixel = u8> + 'static>(img: &ImageBuffer<P, Vec<u8>>) -> Vec<u8> {
+  img.as_raw().clone()
+}
+
+impl Image {
+  pub fn dimensions(&self) -> (u32, u32) {
+    match self {
+      Image::Luma8(img) => img.dimensions(),
+      Image::Rgb8(img) => img.dimensions(),
+      Image::Rgba8(img) => img.dimensions(),
+      Image::Luma16(img) => img.dimensions(),
+      Image::Rgb16(img) => img.dimensions(),
+      Image::Rgba16(img) => img.dimensions(),
+      Image::LumaF32(img) => img.dimensions(),
+      Image::RgbF32(img) => img.dimensions(),
+      Image::RgbaF32(img) => img.dimensions(),
+    }
+  }
+
+  /// Convert to the image crate's `DynamicImage`, for encoders and processing that want one.
+  /// Float images become 16-bit, since that's the best `DynamicImage` can do.
+  pub fn to_dynamic(&self) -> DynamicImage {
+    match self {
+      Image::Luma8(img) => DynamicImage::ImageLuma8(img.clone()),
+      Image::Rgb8(img) => DynamicImage::ImageRgb8(img.clone()),
+      Image::Rgba8(img) => DynamicImage::ImageRgba8(img.clone()),
+      Image::Luma16(img) => DynamicImage::ImageLuma16(img.clone()),
+      Image::Rgb16(img) => DynamicImage::ImageRgb16(img.clone()),
+      Image::Rgba16(img) => DynamicImage::ImageRgba16(img.clone()),
+      Image::LumaF32(_) | Image::RgbF32(_) | Image::RgbaF32(_) => self.to_depth(Depth::Sixteen).to_dynamic(),
+    }
+  }
+
+  /// The raw bytes and color type to hand to an encoder like PNG's, which wants 16-bit samples big-endian.
+  fn encodable(&self) -> (Vec<u8>, ColorType) {
+    match self {
+      Image::Luma8(img) => (raw_bytes(img), ColorType::L8),
+      Image::Rgb8(img) => (raw_bytes(img), ColorType::Rgb8),
+      Image::Rgba8(img) => (raw_bytes(img), ColorType::Rgba8),
+      Image::Luma16(img) => (be_bytes(img), ColorType::L16),
+      Image::Rgb16(img) => (be_bytes(img), ColorType::Rgb16),
+      Image::Rgba16(img) => (be_bytes(img), ColorType::Rgba16),
+      Image::LumaF32(_) | Image::RgbF32(_) | Image::RgbaF32(_) => self.to_depth(Depth::Sixteen).encodable(),
+    }
+  }
+
+  /// The color type `encodable` gives.
+  fn encodable_color(&self) -> ColorType {
+    match (self.channels(), self.depth()) {
+      (1, Depth::Eight) => ColorType::L8,
+      (3, Depth::Eight) => ColorType::Rgb8,
+      (_, Depth::Eight) => ColorType::Rgba8,
+      (1, _) => ColorType::L16,
+      (3, _) => ColorType::Rgb16,
+      (_, _) => ColorType::Rgba16,
+    }
+  }
+
+  /// How many channels each pixel has: 1 for grayscale, 3 for RGB, 4 for RGBA.
+  pub fn channels(&self) -> usize {
+    match self {
+      Image::Luma8(_) | Image::Luma16(_) | Image::LumaF32(_) => 1,
+      Image::Rgb8(_) | Image::Rgb16(_) | Image::RgbF32(_) => 3,
+      Image::Rgba8(_) | Image::Rgba16(_) | Image::RgbaF32(_) => 4,
+    }
+  }
+
+  /// How precisely each channel is stored.
+  pub fn depth(&self) -> Depth {
+    match self {
+      Image::Luma8(_) | Image::Rgb8(_) | Image::Rgba8(_) => Depth::Eight,
+      Image::Luma16(_) | Image::Rgb16(_) | Image::Rgba16(_) => Depth::Sixteen,
+      Image::LumaF32(_) | Image::RgbF32(_) | Image::RgbaF32(_) => Depth::Float,
+    }
+  }
+
+  /// Every subpixel, row by row, converted with whichever function matches the image's depth.
+  fn map_subpixels<T>(&self, eight: impl Fn(u8) -> T, sixteen: impl Fn(u16) -> T, float: impl Fn(f32) -> T) -> Vec<T> {
+    match self {
+      Image::Luma8(img) => img.as_raw().iter().map(|&v| eight(v)).collect(),
+      Image::Rgb8(img) => img.as_raw().iter().map(|&v| eight(v)).collect(),
+      Image::Rgba8(img) => img.as_raw().iter().map(|&v| eight(v)).collect(),
+      Image::Luma16(img) => img.as_raw().iter().map(|&v| sixteen(v)).collect(),
+      Image::Rgb16(img) => img.as_raw().iter().map(|&v| sixteen(v)).collect(),
+      Image::Rgba16(img) => img.as_raw().iter().map(|&v| sixteen(v)).collect(),
+      Image::LumaF32(img) => img.as_raw().iter().map(|&v| float(v)).collect(),
+      Image::RgbF32(img) => img.as_raw().iter().map(|&v| float(v)).collect(),
+      Image::RgbaF32(img) => img.as_raw().iter().map(|&v| float(v)).collect(),
+    }
+  }
+
+  /// Every subpixel as a float, nominally from 0 to 1.
+  fn float_subpixels(&self) -> Vec<f32> {
+    self.map_subpixels(|v| v as f32 / 255.0, |v| v as f32 / u16::MAX as f32, |v| v)
+  }
+
+  /// Convert to a different depth, keeping the channels as they are.
+  /// Going down to 8 bits truncates rather than rounds, which is what generators always did when they picked the
+  /// depth themselves, so old recipes still reproduce exactly.
+  pub fn to_depth(&self, depth: Depth) -> Image {
+    macro_rules! rebuild {
+      ($data:expr; $gray:ident, $rgb:ident, $rgba:ident) => {{
+        let (width, height) = self.dimensions();
+        match self.channels() {
+          1 => Image::$gray(buffer(width, height, $data)),
+          3 => Image::$rgb(buffer(width, height, $data)),
+          _ => Image::$rgba(buffer(width, height, $data)),
+        }
+      }};
+    }
+    if depth == self.depth() {
+      return self.clone();
+    }
+    match depth {
+      Depth::Eight => rebuild!(
+        self.map_subpixels(|v| v, |v| (v >> 8) as u8, |v| (v * 255.0) as u8);
+        Luma8, Rgb8, Rgba8
+      ),
+      Depth::Sixteen => rebuild!(
+        self.map_subpixels(|v| v as u16 * 257, |v| v, |v| (v.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16);
+        Luma16, Rgb16, Rgba16
+      ),
+      Depth::Float => rebuild!(self.float_subpixels(); LumaF32, RgbF32, RgbaF32),
+    }
+  }
+
+  /// Squash down to 8 bits per channel, for formats that can't do any better, keeping the channels as they are.
+  fn to_eight_bit(&self) -> DynamicImage {
+    let dynamic = self.to_dynamic();
+    match dynamic.color().channel_count() {
+      1 => DynamicImage::ImageLuma8(dynamic.to_luma8()),
+      3 => DynamicImage::ImageRgb8(dynamic.to_rgb8()),
+      _ => DynamicImage::ImageRgba8(dynamic.to_rgba8()),
+    }
+  }
+}
+
+macro_rules! image_from {
+  ($($variant:ident: $type:ty),+) => {
+    $(
+      impl From<$type> for Image {
+        fn from(img: $type) -> Image {
+          Image::$variant(img)
+        }
+      }
+    )+
+  };
+}
+
+image_from!{
+  Luma8: GrayImage, Rgb8: RgbImage, Rgba8: RgbaImage,
+  Luma16: Gray16Image, Rgb16: Rgb16Image, Rgba16: Rgba16Image,
+  LumaF32: GrayF32Image, RgbF32: RgbF32Image, RgbaF32: RgbaF32Image
+}
+
+/// Everything a generator can produce.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output {
+  /// An image, which will be encoded by the output stage
+  Image(Image),
+  /// Bytes which are written out exactly as they are, e.g. text from the test generators
+  Raw(Vec<u8>),
+}
+
+impl<T: Into<Image>> From<T> for Output {
+  fn from(img: T) -> Output {
+    Output::Image(img.into())
+  }
+}
+
+/// Build an image buffer from a flat list of subpixels, row by row.
+/// Panics if there isn't exactly the right amount of data, since that's a bug in the generator.
+pub fn buffer<P: Pixel + 'static>(width: u32, height: u32, data: Vec<P::Subpixel>) -> ImageBuffer<P, Vec<P::Subpixel>>
+where
+  P::Subpixel: Primitive,
+{
+  ImageBuffer::from_raw(width, height, data).expect("image data should fit the image exactly")
+}
+
+/// How precisely each channel of an image is stored, from least to most precise.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Depth {
+  Eight,
+  Sixteen,
+  /// 32-bit floats
+  Float,
+}
+
+impl Depth {
+  pub fn all() -> [Depth; 3] {
+    [ Depth::Eight, Depth::Sixteen, Depth::Float ]
+  }
+
+  /// The name to pick this depth by, e.g. with `--depth`
+  pub fn name(&self) -> &'static str {
+    match self {
+      Depth::Eight => "8",
+      Depth::Sixteen => "16",
+      Depth::Float => "32f",
+    }
+  }
+
+  pub fn by_name(name: &str) -> Option<Depth> {
+    Depth::all().iter().copied().find(|depth| depth.name() == name)
+  }
+}
+
+/// The file formats images can be written in: everything the image crate can encode, plus a couple of float formats
+/// it can't.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Format {
+  Png,
+  Jpeg,
+  Bmp,
+  Gif,
+  Ico,
+  Tga,
+  Tiff,
+  Pnm,
+  Farbfeld,
+  /// Radiance HDR
+  Hdr,
+  /// Portable float map
+  Pfm,
+  /// OpenEXR, uncompressed
+  Exr,
+}
+
+impl Format {
+  pub fn all() -> [Format; 12] {
+    [
+      Format::Png, Format::Jpeg, Format::Bmp, Format::Gif, Format::Ico, Format::Tga,
+      Format::Tiff, Format::Pnm, Format::Farbfeld, Format::Hdr, Format::Pfm, Format::Exr,
+    ]
+  }
+
+  /// The name to pick this format by, e.g. with `--format`
+  pub fn name(&self) -> &'static str {
+    self.extensions()[0]
+  }
+
+  /// The file extensions which mean this format, the first being the preferred one
+  pub fn extensions(&self) -> &'static [&'static str] {
+    match self {
+      Format::Png => &["png"],
+      Format::Jpeg => &["jpeg", "jpg"],
+      Format::Bmp => &["bmp"],
+      Format::Gif => &["gif"],
+      Format::Ico => &["ico"],
+      Format::Tga => &["tga"],
+      Format::Tiff => &["tiff", "tif"],
+      Format::Pnm => &["pnm", "pbm", "pgm", "ppm", "pam"],
+      Format::Farbfeld => &["farbfeld", "ff"],
+      Format::Hdr => &["hdr"],
+      Format::Pfm => &["pfm"],
+      Format::Exr => &["exr"],
+    }
+  }
+
+  /// The most precise depth the format can hold. Lower depths are written at this one, without losing anything.
+  pub fn max_depth(&self) -> Depth {
+    match self {
+      Format::Png | Format::Tiff | Format::Farbfeld => Depth::Sixteen,
+      Format::Hdr | Format::Pfm | Format::Exr => Depth::Float,
+      _ => Depth::Eight,
+    }
+  }
+
+  /// The depth to write at when none is asked for: floats for the float formats, since that's the point of them, and
+  /// 8 bits for everything else.
+  pub fn default_depth(&self) -> Depth {
+    match self.max_depth() {
+      Depth::Float => Depth::Float,
+      _ => Depth::Eight,
+    }
+  }
+
+  /// Whether the format can carry a recipe along with the image
+  pub fn holds_recipe(&self) -> bool {
+    *self == Format::Png
+  }
+
+  /// Look up a format by name or by any of its extensions, ignoring case.
+  pub fn by_name(name: &str) -> Option<Format> {
+    let name = name.to_ascii_lowercase();
+    Format::all().iter().copied().find(|format| format.extensions().contains(&name.as_str()))
+  }
+
+  /// Figure out the format from a path's extension. `None` if there's no extension or it isn't one we know.
+  pub fn from_path(path: &Path) -> Option<Format> {
+    Format::by_name(path.extension()?.to_str()?)
+  }
+}
+
+/// The names `png_compression` understands, the default first.
+pub const PNG_COMPRESSIONS: &[&str] = &["fast", "default", "best", "huffman", "rle"];
+
+/// The names `png_filter` understands, the default first.
+pub const PNG_FILTERS: &[&str] = &["sub", "none", "up", "avg", "paeth"];
+
+pub fn png_compression(name: &str) -> Option<CompressionType> {
+  match name {
+    "fast" => Some(CompressionType::Fast),
+    "default" => Some(CompressionType::Default),
+    "best" => Some(CompressionType::Best),
+    "huffman" => Some(CompressionType::Huffman),
+    "rle" => Some(CompressionType::Rle),
+    _ => None,
+  }
+}
+
+pub fn png_filter(name: &str) -> Option<FilterType> {
+  match name {
+    "sub" => Some(FilterType::Sub),
+    "none" => Some(FilterType::NoFilter),
+    "up" => Some(FilterType::Up),
+    "avg" => Some(FilterType::Avg),
+    "paeth" => Some(FilterType::Paeth),
+    _ => None,
+  }
+}
+
+/// How to encode an image: the format and depth, plus the knobs for the formats that have any.
+/// Options for formats other than the chosen one are ignored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Encoding {
+  pub format: Format,
+  /// The depth images are converted to before being written
+  pub depth: Depth,
+  /// JPEG quality, from 1 to 100
+  pub jpeg_quality: u8,
+  pub png_compression: CompressionType,
+  pub png_filter: FilterType,
+  /// Set when nothing said what format to write and the output's extension isn't one we know, so `format` is only a
+  /// guess. Raw output doesn't care, but images can't be written like that; see `check_path`.
+  pub guessed: bool,
+}
+
+impl Default for Encoding {
+  fn default() -> Encoding {
+    Encoding {
+      format: Format::Png,
+      depth: Depth::Eight,
+      jpeg_quality: 90,
+      png_compression: CompressionType::Fast,
+      png_filter: FilterType::Sub,
+      guessed: false,
+    }
+  }
+}
+
+impl Encoding {
+  /// The default options for a particular format.
+  pub fn new(format: Format) -> Encoding {
+    Encoding { format, depth: format.default_depth(), ..Encoding::default() }
+  }
+
+  /// Make sure the format can actually hold the depth.
+  pub fn check(&self) -> std::result::Result<(), String> {
+    if self.depth > self.format.max_depth() {
+      Err(format!("{} can't hold {}-bit images", self.format.name(), self.depth.name()))
+    } else {
+      Ok(())
+    }
+  }
+
+  /// Make sure an image can be written to `path`, which it can't if the format's only a guess.
+  pub fn check_path(&self, path: &Path) -> std::result::Result<(), String> {
+    if !self.guessed {
+      return Ok(());
+    }
+    let extension = path.extension().unwrap_or_default().to_string_lossy().to_ascii_lowercase();
+    match extension.as_str() {
+      "webp" => Err("hart can't write WebP images; pass --format to pick another format".to_string()),
+      _ => Err(format!("unknown format '{}'; pass --format", extension)),
+    }
+  }
+}
+
+/// Encode an image in whatever format and depth was asked for, converting its pixels to something that format can
+/// hold.
+fn encode(img: &Image, encoding: &Encoding, mut output: &mut dyn Write) -> Result<()> {
+  encoding.check().map_err(GenFail::BadArg)?;
+  let img = &img.to_depth(encoding.depth);
+  let (width, height) = img.dimensions();
+  match encoding.format {
+    Format::Png => {
+      let (data, color) = img.encodable();
+      PngEncoder::new_with_quality(output, encoding.png_compression, encoding.png_filter)
+        .encode(&data, width, height, color)?;
+    }
+    Format::Jpeg => {
+      let eight = img.to_eight_bit();
+      JpegEncoder::new_with_quality(&mut output, encoding.jpeg_quality)
+        .encode(eight.as_bytes(), width, height, eight.color())?;
+    }
+    Format::Bmp => {
+      let eight = img.to_eight_bit();
+      BmpEncoder::new(&mut output).encode(eight.as_bytes(), width, height, eight.color())?;
+    }
+    Format::Gif => {
+      let rgba = img.to_dynamic().to_rgba8();
+      GifEncoder::new(output).encode(&rgba, width, height, ColorType::Rgba8)?;
+    }
+    Format::Ico => {
+      // icons are always RGBA, or at least plenty of readers (the image crate included) insist on it
+      let rgba = img.to_dynamic().to_rgba8();
+      IcoEncoder::new(output).encode(&rgba, width, height, ColorType::Rgba8)?;
+    }
+    Format::Tga => {
+      let eight = img.to_eight_bit();
+      TgaEncoder::new(output).encode(eight.as_bytes(), width, height, eight.color())?;
+    }
+    Format::Tiff => {
+      // the TIFF encoder needs to seek, so it can't go straight to the output
+      let dynamic = img.to_dynamic();
+      let mut tiff = Cursor::new(Vec::new());
+      TiffEncoder::new(&mut tiff).encode(dynamic.as_bytes(), width, height, dynamic.color())?;
+      output.write_all(tiff.get_ref())?;
+    }
+    Format::Pnm => {
+      let eight = img.to_eight_bit();
+      PnmEncoder::new(output).encode(eight.as_bytes(), width, height, eight.color())?;
+    }
+    Format::Farbfeld => {
+      let rgba = DynamicImage::ImageRgba16(img.to_dynamic().to_rgba16());
+      FarbfeldEncoder::new(output).encode(rgba.as_bytes(), width, height)?;
+    }
+    Format::Hdr => {
+      let rgb = img.float_subpixels();
+      let pixels: Vec<_> = float::rgb(&rgb, img.channels()).chunks(3).map(|px| Rgb([px[0], px[1], px[2]])).collect();
+      HdrEncoder::new(output).encode(&pixels, width as usize, height as usize)?;
+    }
+    Format::Pfm => {
+      let data = img.float_subpixels();
+      float::write_pfm(width, height, img.channels(), &data, output)?;
+    }
+    Format::Exr => {
+      let data = img.float_subpixels();
+      float::write_exr(width, height, img.channels(), &data, output)?;
+    }
+  }
+  Ok(())
+}
+
+/// Write a generator's output. Images are encoded as asked, with the recipe embedded if there is one and the format
+/// can hold it; raw output is written as-is no matter what.
+pub fn write(out: &Output, recipe: Option<&Recipe>, encoding: &Encoding, output: &mut dyn Write) -> Result<()> {
+  let _span = Span::enter("encode");
+  match (out, recipe) {
+    (Output::Raw(data), _) => output.write_all(data)?,
+    (Output::Image(img), Some(recipe)) if encoding.format.holds_recipe() => {
+      let mut png = Vec::new();
+      encode(img, encoding, &mut png)?;
+      let embedded = recipe.embed(&png).expect("the PNG encoder produced a malformed PNG");
+      output.write_all(&embedded)?;
+    }
+    (Output::Image(img), _) => encode(img, encoding, output)?,
+  }
+  Ok(())
+}
+
+/// How many rows to render and encode at a time when streaming: enough to keep every core busy.
+fn band_rows(renderer: &Renderer) -> u32 {
+  renderer.tile_height() * rayon::current_num_threads() as u32
+}
+
+/// Render a view of a raster and encode it as it goes, a band of rows at a time, so only a couple of bands are ever in
+/// memory. The next band renders while the last one is being encoded. Only PNG can be written this way.
+pub fn stream(
+  raster: &dyn Raster, view: View, recipe: Option<&Recipe>, encoding: &Encoding, output: &mut dyn Write,
+) -> Result<()> {
+  if encoding.format != Format::Png {
+    return Err(GenFail::BadArg(format!("only PNGs can be streamed, not {}", encoding.format.name())));
+  }
+  encoding.check().map_err(GenFail::BadArg)?;
+
+  let renderer = Renderer::new().quiet();
+  let View { area, scale } = view;
+  let rows = band_rows(&renderer);
+  let mut bands = (area.y..area.y + area.height).step_by(rows as usize)
+    .map(|y| Tile { x: area.x, y, width: area.width, height: rows.min(area.y + area.height - y) });
+  let render = |band: Tile| raster.render(&renderer, View { area: band, scale }).to_depth(encoding.depth);
+
+  let _span = Span::enter("generate and encode");
+  Progress::global().start(area.width as u64 * area.height as u64);
+  let first = match bands.next() {
+    Some(band) => render(band),
+    None => return Err(GenFail::BadArg("can't stream an empty image".to_string())),
+  };
+  let mut png = PngStream::new(output, area.width, area.height, first.encodable_color(), encoding, recipe)?;
+  let mut next = Some(first);
+  while let Some(band) = next.take() {
+    next = thread::scope(|s| -> Result<_> {
+      let rendering = s.spawn(|| bands.next().map(render));
+      png.write_rows(&band.encodable().0)?;
+      Ok(rendering.join().expect("rendering panicked"))
+    })?;
+  }
+  png.finish()?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    image::GenericImageView as _,
+  };
+
+  fn encoded(img: Image, encoding: Encoding) -> Vec<u8> {
+    let mut data = Vec::new();
+    write(&Output::Image(img), None, &encoding, &mut data).unwrap();
+    data
+  }
+
+  /// Encode as 16-bit PNG and decode it again.
+  fn round_trip(img: Image) -> DynamicImage {
+    image::load_from_memory(&encoded(img, Encoding { depth: Depth::Sixteen, ..Encoding::new(Format::Png) })).unwrap()
+  }
+
+  #[test]
+  fn eight_bit_round_trips() {
+    let img = buffer::<Rgb<u8>>(2, 1, vec![1, 2, 3, 250, 251, 252]);
+    assert_eq!(round_trip(img.clone().into()).to_rgb8(), img);
+  }
+
+  #[test]
+  fn sixteen_bit_round_trips() {
+    let img = buffer::<Luma<u16>>(3, 1, vec![0, 0x1234, 0xFFFF]);
+    assert_eq!(round_trip(img.clone().into()).to_luma16(), img);
+  }
+
+  #[test]
+  fn floats_become_sixteen_bit() {
+    let img = buffer::<Luma<f32>>(4, 1, vec![-1.0, 0.0, 0.5, 2.0]);
+    let decoded = round_trip(img.into());
+    assert_eq!(decoded.color(), ColorType::L16);
+    assert_eq!(decoded.to_luma16().into_raw(), vec![0, 0, 32768, 65535]);
+  }
+
+  #[test]
+  fn raw_is_untouched() {
+    let mut out = Vec::new();
+    write(&Output::Raw(b"hello".to_vec()), None, &Encoding::new(Format::Jpeg), &mut out).unwrap();
+    assert_eq!(out, b"hello");
+  }
+
+  #[test]
+  fn formats_come_from_extensions() {
+    assert_eq!(Format::from_path(Path::new("out/image.JPG")), Some(Format::Jpeg));
+    assert_eq!(Format::from_path(Path::new("image.tif")), Some(Format::Tiff));
+    assert_eq!(Format::from_path(Path::new("image.txt")), None);
+    assert_eq!(Format::from_path(Path::new("image")), None);
+    for format in Format::all().iter() {
+      assert_eq!(Format::by_name(format.name()), Some(*format));
+    }
+  }
+
+  #[test]
+  fn guessed_formats_are_refused_for_images() {
+    let guessed = Encoding { guessed: true, ..Encoding::default() };
+    let unknown = guessed.check_path(Path::new("out.jpgg"));
+    assert_eq!(unknown.unwrap_err(), "unknown format 'jpgg'; pass --format");
+    assert!(guessed.check_path(Path::new("out.WebP")).unwrap_err().starts_with("hart can't write WebP"));
+    assert!(Encoding::default().check_path(Path::new("out.jpgg")).is_ok());
+  }
+
+  #[test]
+  fn every_format_decodes() {
+    let img = buffer::<Rgb<u8>>(2, 2, vec![0, 0, 0, 255, 0, 0, 0, 255, 0, 255, 255, 255]);
+    // the image crate can't read these, so they have their own tests
+    let unreadable = [Format::Pfm, Format::Exr];
+    for format in Format::all().iter().filter(|f| !unreadable.contains(f)) {
+      let data = encoded(img.clone().into(), Encoding::new(*format));
+      // TGA has no magic number, so go by extension rather than sniffing
+      let image_format = image::ImageFormat::from_extension(format.extensions().last().unwrap()).unwrap();
+      let decoded = image::load_from_memory_with_format(&data, image_format).unwrap_or_else(|e| panic!("{} failed to decode: {}", format.name(), e));
+      assert_eq!(decoded.dimensions(), (2, 2), "{} has the wrong size", format.name());
+      if ![Format::Jpeg, Format::Gif, Format::Hdr].contains(format) {
+        assert_eq!(decoded.to_rgb8(), img, "{} isn't lossless", format.name());
+      }
+    }
+  }
+
+  #[test]
+  fn sixteen_bit_survives_where_possible() {
+    let img = buffer::<Rgb<u16>>(2, 1, vec![0, 0x1234, 0xFFFF, 0x8000, 1, 2]);
+    for &format in [Format::Png, Format::Tiff, Format::Farbfeld].iter() {
+      let encoding = Encoding { depth: Depth::Sixteen, ..Encoding::new(format) };
+      let decoded = image::load_from_memory(&encoded(img.clone().into(), encoding)).unwrap();
+      assert_eq!(decoded.to_rgb16(), img, "{} lost precision", format.name());
+    }
+  }
+
+  #[test]
+  fn depths_convert_both_ways() {
+    let img: Image = buffer::<Luma<f32>>(4, 1, vec![0.0, 0.2, 0.5, 1.0]).into();
+    assert_eq!(img.to_depth(Depth::Eight), buffer::<Luma<u8>>(4, 1, vec![0, 51, 127, 255]).into());
+    assert_eq!(img.to_depth(Depth::Sixteen), buffer::<Luma<u16>>(4, 1, vec![0, 13107, 32768, 65535]).into());
+    let eight: Image = buffer::<Rgba<u8>>(1, 1, vec![0, 1, 128, 255]).into();
+    assert_eq!(eight.to_depth(Depth::Sixteen).to_depth(Depth::Eight), eight);
+    assert_eq!(eight.to_depth(Depth::Float).to_depth(Depth::Eight), eight);
+  }
+
+  #[test]
+  fn formats_refuse_depths_they_cant_hold() {
+    let img: Image = buffer::<Luma<f32>>(1, 1, vec![0.5]).into();
+    for &(format, depth) in [(Format::Jpeg, Depth::Sixteen), (Format::Png, Depth::Float)].iter() {
+      let encoding = Encoding { depth, ..Encoding::new(format) };
+      assert!(encoding.check().is_err());
+      match write(&Output::Image(img.clone()), None, &encoding, &mut Vec::new()) {
+        Err(GenFail::BadArg(_)) => (),
+        other => panic!("expected a BadArg, got {:?}", other),
+      }
+    }
+    assert!(Encoding { depth: Depth::Eight, ..Encoding::new(Format::Exr) }.check().is_ok());
+  }
+
+  #[test]
+  fn float_formats_keep_precision() {
+    let value = 0.123_456_7;
+    let img: Image = buffer::<Luma<f32>>(1, 1, vec![value]).into();
+    let pfm = encoded(img.clone(), Encoding::new(Format::Pfm));
+    assert_eq!(&pfm[pfm.len() - 4..], &value.to_le_bytes());
+    let exr = encoded(img, Encoding::new(Format::Exr));
+    assert_eq!(&exr[exr.len() - 4..], &value.to_le_bytes());
+  }
+
+  #[test]
+  fn streaming_matches_rendering_all_at_once() {
+    use crate::utils::render::Pixels;
+    let raster = Pixels::new(70, 300, |x, y| Rgb([x / 70.0, y / 300.0, 0.5]));
+    for &depth in [Depth::Eight, Depth::Sixteen].iter() {
+      let encoding = Encoding { depth, ..Encoding::default() };
+      let mut streamed = Vec::new();
+      stream(&raster, View::whole(70, 300), None, &encoding, &mut streamed).unwrap();
+      let whole = (&raster as &dyn Raster).render_all(&Renderer::new().quiet());
+      let expected = whole.to_depth(depth).to_dynamic();
+      assert_eq!(image::load_from_memory(&streamed).unwrap(), expected);
+    }
+  }
+
+  #[test]
+  fn streaming_several_bands_matches_encoding_at_once() {
+    use crate::utils::render::Pixels;
+    // noisy, so the filters and compression have something to do across the joins between bands
+    let noise = |x: u32, y: u32| ((x * 7 + y * 13).wrapping_mul(2_654_435_761) >> 24) as u8;
+    let height = band_rows(&Renderer::new().quiet()) * 2 + 5;
+    let raster = Pixels::new(33, height, move |x: f32, y: f32| Rgb([noise(x as u32, y as u32), y as u8, 9]));
+    let encoding = Encoding { png_filter: FilterType::Paeth, ..Encoding::default() };
+    let mut streamed = Vec::new();
+    stream(&raster, View::whole(33, height), None, &encoding, &mut streamed).unwrap();
+    let mut encoded = Vec::new();
+    encode(&(&raster as &dyn Raster).render_all(&Renderer::new().quiet()), &encoding, &mut encoded).unwrap();
+    assert_eq!(image::load_from_memory(&streamed).unwrap(), image::load_from_memory(&encoded).unwrap());
+  }
+
+  #[test]
+  fn only_png_streams() {
+    let raster = crate::utils::render::Pixels::new(4, 4, |_, _| Luma([0u8]));
+    match stream(&raster, View::whole(4, 4), None, &Encoding::new(Format::Jpeg), &mut Vec::new()) {
+      Err(GenFail::BadArg(_)) => (),
+      other => panic!("expected a BadArg, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn jpeg_quality_matters() {
+    let data: Vec<u8> = (0..64 * 64 * 3).map(|i| (i * 7 % 251) as u8).collect();
+    let img: Image = buffer::<Rgb<u8>>(64, 64, data).into();
+    let size = |quality| {
+      let mut out = Vec::new();
+      let encoding = Encoding { jpeg_quality: quality, ..Encoding::new(Format::Jpeg) };
+      write(&Output::Image(img.clone()), None, &encoding, &mut out).unwrap();
+      out.len()
+    };
+    assert!(size(10) < size(95));
+  }
+}
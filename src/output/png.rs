@@ -0,0 +1,230 @@
+//! A PNG encoder which takes the image a few rows at a time, so huge images never have to be in memory at once.
+//!
+//! The image crate's encoder wants the whole image up front, and the `png` crate's own `StreamWriter` (in 0.16.8, the
+//! version the image crate uses) can corrupt the image: its `ChunkWriter` writes out a full IDAT as soon as it's one
+//! byte short of full, stale last byte and all, and `flush` writes `buffer[..=index]`, a byte more than it has. A
+//! 485x97 grayscale image with RLE compression is enough to break it. So this does the (simple) job itself: filter
+//! each row, deflate them, and cut the compressed stream into IDAT chunks as it goes.
+
+use {
+  super::Encoding,
+  crate::recipe::{chunk, Recipe, PNG_SIG},
+  deflate::{write::ZlibEncoder, CompressionOptions},
+  image::{
+    codecs::png::{CompressionType, FilterType},
+    ColorType,
+  },
+  std::io::{self, Write},
+};
+
+/// How much compressed data to gather before writing it out as an IDAT chunk.
+const IDAT_SIZE: usize = 64 * 1024;
+
+/// Collects compressed data and writes it out in IDAT chunks.
+struct Idat<W: Write> {
+  output: W,
+  buffer: Vec<u8>,
+}
+
+impl<W: Write> Write for Idat<W> {
+  fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+    self.buffer.extend_from_slice(data);
+    if self.buffer.len() >= IDAT_SIZE {
+      self.flush()?;
+    }
+    Ok(data.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    if !self.buffer.is_empty() {
+      self.output.write_all(&chunk(b"IDAT", &self.buffer))?;
+      self.buffer.clear();
+    }
+    self.output.flush()
+  }
+}
+
+/// The numbers PNG uses for color types and bit depths in its header.
+fn png_color(color: ColorType) -> (u8, u8) {
+  match color {
+    ColorType::L8 => (0, 8),
+    ColorType::Rgb8 => (2, 8),
+    ColorType::La8 => (4, 8),
+    ColorType::Rgba8 => (6, 8),
+    ColorType::L16 => (0, 16),
+    ColorType::Rgb16 => (2, 16),
+    ColorType::La16 => (4, 16),
+    ColorType::Rgba16 => (6, 16),
+    other => panic!("PNG can't hold {:?}", other),
+  }
+}
+
+fn filter_number(filter: FilterType) -> u8 {
+  match filter {
+    FilterType::NoFilter => 0,
+    FilterType::Sub => 1,
+    FilterType::Up => 2,
+    FilterType::Avg => 3,
+    FilterType::Paeth => 4,
+    _ => 1,
+  }
+}
+
+fn compression_options(compression: CompressionType) -> CompressionOptions {
+  match compression {
+    CompressionType::Default => CompressionOptions::default(),
+    CompressionType::Best => CompressionOptions::high(),
+    CompressionType::Huffman => CompressionOptions::huffman_only(),
+    CompressionType::Rle => CompressionOptions::rle(),
+    _ => CompressionOptions::fast(),
+  }
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+  let p = left as i16 + up as i16 - up_left as i16;
+  let (pa, pb, pc) = ((p - left as i16).abs(), (p - up as i16).abs(), (p - up_left as i16).abs());
+  if pa <= pb && pa <= pc {
+    left
+  } else if pb <= pc {
+    up
+  } else {
+    up_left
+  }
+}
+
+/// Filter one row against the one above it (all zeroes for the first row). `bpp` is bytes per pixel.
+fn filter_row(filter: u8, bpp: usize, prev: &[u8], row: &[u8], out: &mut Vec<u8>) {
+  out.push(filter);
+  for i in 0..row.len() {
+    let left = if i >= bpp { row[i - bpp] } else { 0 };
+    let up_left = if i >= bpp { prev[i - bpp] } else { 0 };
+    let predicted = match filter {
+      0 => 0,
+      1 => left,
+      2 => prev[i],
+      3 => ((left as u16 + prev[i] as u16) / 2) as u8,
+      _ => paeth(left, prev[i], up_left),
+    };
+    out.push(row[i].wrapping_sub(predicted));
+  }
+}
+
+/// A PNG being written out a few rows at a time.
+pub struct PngStream<W: Write> {
+  zlib: ZlibEncoder<Idat<W>>,
+  filter: u8,
+  bpp: usize,
+  row_len: usize,
+  rows_left: u32,
+  prev: Vec<u8>,
+  filtered: Vec<u8>,
+}
+
+impl<W: Write> PngStream<W> {
+  /// Write the header (and the recipe, if there is one), ready for the image data.
+  pub fn new(
+    mut output: W, width: u32, height: u32, color: ColorType, encoding: &Encoding, recipe: Option<&Recipe>,
+  ) -> io::Result<PngStream<W>> {
+    let (color_type, depth) = png_color(color);
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    // compression, filter method, and interlacing are always 0
+    ihdr.extend_from_slice(&[depth, color_type, 0, 0, 0]);
+    output.write_all(PNG_SIG)?;
+    output.write_all(&chunk(b"IHDR", &ihdr))?;
+    if let Some(recipe) = recipe {
+      output.write_all(&recipe.png_chunks())?;
+    }
+
+    let row_len = width as usize * color.bytes_per_pixel() as usize;
+    let idat = Idat { output, buffer: Vec::with_capacity(IDAT_SIZE) };
+    Ok(PngStream {
+      zlib: ZlibEncoder::new(idat, compression_options(encoding.png_compression)),
+      filter: filter_number(encoding.png_filter),
+      bpp: color.bytes_per_pixel() as usize,
+      row_len,
+      rows_left: height,
+      prev: vec![0; row_len],
+      filtered: Vec::with_capacity(row_len + 1),
+    })
+  }
+
+  /// Write some whole rows of raw pixel data, with 16-bit samples big-endian.
+  pub fn write_rows(&mut self, data: &[u8]) -> io::Result<()> {
+    assert_eq!(data.len() % self.row_len, 0, "PNG data has to be whole rows");
+    for row in data.chunks(self.row_len) {
+      assert!(self.rows_left > 0, "more rows than the PNG has room for");
+      self.rows_left -= 1;
+      self.filtered.clear();
+      filter_row(self.filter, self.bpp, &self.prev, row, &mut self.filtered);
+      self.zlib.write_all(&self.filtered)?;
+      self.prev.copy_from_slice(row);
+    }
+    Ok(())
+  }
+
+  /// Finish off the compressed data and the file, once every row has been written.
+  pub fn finish(self) -> io::Result<W> {
+    assert_eq!(self.rows_left, 0, "PNG finished before every row was written");
+    let mut idat = self.zlib.finish()?;
+    idat.flush()?;
+    let Idat { mut output, .. } = idat;
+    output.write_all(&chunk(b"IEND", &[]))?;
+    Ok(output)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    image::{DynamicImage, GenericImageView as _},
+  };
+
+  fn streamed(data: &[u8], width: u32, height: u32, color: ColorType, filter: FilterType, rows: usize) -> DynamicImage {
+    let encoding = Encoding { png_filter: filter, ..Encoding::default() };
+    let mut stream = PngStream::new(Vec::new(), width, height, color, &encoding, None).unwrap();
+    let row_len = width as usize * color.bytes_per_pixel() as usize;
+    for part in data.chunks(row_len * rows) {
+      stream.write_rows(part).unwrap();
+    }
+    image::load_from_memory(&stream.finish().unwrap()).unwrap()
+  }
+
+  #[test]
+  fn every_filter_round_trips() {
+    let data: Vec<u8> = (0..37 * 23 * 3).map(|i| ((i * 31) ^ (i / 7)) as u8).collect();
+    for &filter in [FilterType::NoFilter, FilterType::Sub, FilterType::Up, FilterType::Avg, FilterType::Paeth].iter() {
+      let img = streamed(&data, 37, 23, ColorType::Rgb8, filter, 5);
+      assert_eq!(img.dimensions(), (37, 23));
+      assert_eq!(img.to_rgb8().into_raw(), data, "{:?} filter broke the image", filter);
+    }
+  }
+
+  #[test]
+  fn sixteen_bit_is_big_endian() {
+    let data = [0x12, 0x34, 0xff, 0x00];
+    let img = streamed(&data, 2, 1, ColorType::L16, FilterType::Sub, 1);
+    assert_eq!(img.to_luma16().into_raw(), vec![0x1234, 0xff00]);
+  }
+
+  #[test]
+  fn writes_what_the_png_crate_gets_wrong() {
+    // the png crate's StreamWriter corrupts exactly this one
+    let data: Vec<u8> = (0..485u32 * 97).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+    let encoding = Encoding { png_compression: CompressionType::Rle, ..Encoding::default() };
+    let mut stream = PngStream::new(Vec::new(), 485, 97, ColorType::L8, &encoding, None).unwrap();
+    stream.write_rows(&data).unwrap();
+    let img = image::load_from_memory(&stream.finish().unwrap()).unwrap();
+    assert_eq!(img.to_luma8().into_raw(), data);
+  }
+
+  #[test]
+  fn big_images_span_several_idats() {
+    // noisy enough not to compress down to one chunk's worth
+    let data: Vec<u8> = (0..300u32 * 300).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+    let img = streamed(&data, 300, 300, ColorType::L8, FilterType::Up, 17);
+    assert_eq!(img.to_luma8().into_raw(), data);
+  }
+}
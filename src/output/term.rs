@@ -0,0 +1,63 @@
+//! Showing images right in a terminal, for a quick look without leaving it (or over SSH, where there's nothing else).
+
+use {
+  super::Output,
+  crate::gens::Result,
+  image::{Rgb, RgbImage},
+  std::io::Write,
+};
+
+/// The upper half block. Its foreground color fills the top half of the cell and its background the bottom, so each
+/// character shows two pixels, one above the other.
+const HALF_BLOCK: char = '\u{2580}';
+
+/// Write an image as rows of half blocks, colored with 24-bit ANSI escapes, so every pixel shows up as half a
+/// character. Colors are only written when they change, to keep the output small.
+pub fn half_blocks(img: &RgbImage, out: &mut dyn Write) -> Result<()> {
+  let (width, height) = img.dimensions();
+  for y in (0..height).step_by(2) {
+    let mut last: Option<(Rgb<u8>, Option<Rgb<u8>>)> = None;
+    let mut line = String::new();
+    for x in 0..width {
+      let top = *img.get_pixel(x, y);
+      // an odd row out at the bottom has nothing under it, so it's left the terminal's own background
+      let bottom = if y + 1 < height { Some(*img.get_pixel(x, y + 1)) } else { None };
+      if last != Some((top, bottom)) {
+        let Rgb([r, g, b]) = top;
+        line.push_str(&format!("\x1b[38;2;{};{};{}m", r, g, b));
+        match bottom {
+          Some(Rgb([r, g, b])) => line.push_str(&format!("\x1b[48;2;{};{};{}m", r, g, b)),
+          None => line.push_str("\x1b[49m"),
+        }
+        last = Some((top, bottom));
+      }
+      line.push(HALF_BLOCK);
+    }
+    writeln!(out, "{}\x1b[0m", line)?;
+  }
+  Ok(())
+}
+
+/// Show a generator's output in the terminal: images as half blocks, and anything else as it is.
+pub fn terminal(out: &Output, output: &mut dyn Write) -> Result<()> {
+  match out {
+    Output::Image(img) => half_blocks(&img.to_dynamic().to_rgb8(), output),
+    Output::Raw(data) => Ok(output.write_all(data)?),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn pixels_pair_up_into_half_blocks() {
+    let mut img = RgbImage::from_pixel(2, 3, Rgb([1, 2, 3]));
+    img.put_pixel(1, 1, Rgb([4, 5, 6]));
+    let mut out = vec![];
+    half_blocks(&img, &mut out).unwrap();
+    let expected = "\x1b[38;2;1;2;3m\x1b[48;2;1;2;3m\u{2580}\x1b[38;2;1;2;3m\x1b[48;2;4;5;6m\u{2580}\x1b[0m\n\
+      \x1b[38;2;1;2;3m\x1b[49m\u{2580}\u{2580}\x1b[0m\n";
+    assert_eq!(String::from_utf8(out).unwrap(), expected);
+  }
+}
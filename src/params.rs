@@ -0,0 +1,405 @@
+//! Typed parameters for generators.
+//!
+//! Each generator describes the parameters it takes as a list of [`Param`]s. Frontends (the command line, recipes,
+//! and anything else) turn whatever the user wrote into `(name, text)` pairs, and [`resolve`] checks them against
+//! the schema, fills in defaults, and produces [`Params`], which the generator reads typed values out of.
+//!
+//! Every value has a canonical text form (its `Display`) which parses back to exactly the same value, so resolved
+//! parameters can be written down and replayed later.
+
+use std::{
+  fmt,
+  path::{Path, PathBuf},
+};
+
+/// What kind of value a parameter takes, and which values are allowed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Kind {
+  /// A whole number, optionally limited to an inclusive range
+  Int { min: Option<i64>, max: Option<i64> },
+  /// A real number, optionally limited to an inclusive range
+  Float { min: Option<f64>, max: Option<f64> },
+  /// A percentage, written with or without a trailing `%`, optionally limited to an inclusive range of percentages
+  Percent { min: Option<f64>, max: Option<f64> },
+  /// One of a fixed list of choices
+  Enum(&'static [&'static str]),
+  /// A path to a file, or `-` for stdin
+  Path,
+  /// A switch, which is either on or off
+  Flag,
+  /// A width and height in pixels, written like `1920x1080`
+  Size,
+  /// A point, as percentages of the way across and down, written like `25%,50%`
+  Point,
+  /// A list of points, written like `10%,50%;90%,50%`
+  Points,
+}
+
+/// A parsed, validated parameter value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+  Int(i64),
+  Float(f64),
+  /// Stored as written, e.g. 25% is `Percent(25.0)`
+  Percent(f64),
+  Enum(&'static str),
+  Path(PathBuf),
+  Flag(bool),
+  Size(u32, u32),
+  /// Stored as written, like percentages
+  Point(f64, f64),
+  Points(Vec<(f64, f64)>),
+}
+
+impl fmt::Display for Value {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Value::Int(i) => write!(f, "{}", i),
+      Value::Float(n) => write!(f, "{}", n),
+      Value::Percent(p) => write!(f, "{}%", p),
+      Value::Enum(choice) => write!(f, "{}", choice),
+      Value::Path(path) => write!(f, "{}", path.display()),
+      Value::Flag(on) => write!(f, "{}", on),
+      Value::Size(width, height) => write!(f, "{}x{}", width, height),
+      Value::Point(x, y) => write!(f, "{}%,{}%", x, y),
+      Value::Points(points) => {
+        let points: Vec<_> = points.iter().map(|(x, y)| format!("{}%,{}%", x, y)).collect();
+        write!(f, "{}", points.join(";"))
+      }
+    }
+  }
+}
+
+/// The names of hart's own command line options, which generators' parameters can't use.
+pub const RESERVED: &[&str] = &[
+  "seed", "seed-file", "seed-hex", "seed-b64", "seed-int", "random-seed", "seed-stdin", "seeds", "seed-range", "sweep",
+  "output", "region", "canvas", "tiles", "format", "depth", "stream", "quality", "png-compression", "png-filter",
+  "thumbnail", "columns", "watch", "watch-preview", "preview", "term", "quiet", "verbose", "help",
+];
+
+/// The abbreviations of hart's own command line options, which generators' parameters can't use.
+pub const RESERVED_SHORTS: &[char] = &['o', 'q', 'v', 'h'];
+
+/// The description of a single parameter a generator takes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+  /// The parameter's name, which is also its long command line option. Anything in `RESERVED` is taken.
+  pub name: &'static str,
+  /// A single-character abbreviation for the command line, if it has one
+  pub short: Option<char>,
+  /// A short, human-friendly description
+  pub help: &'static str,
+  /// What kind of value it takes
+  pub kind: Kind,
+  /// The value used if none is given, in text form
+  pub default: Option<&'static str>,
+  /// Whether a value has to be given. Meaningless if there's a default.
+  pub required: bool,
+}
+
+fn parse_bound<T: PartialOrd + fmt::Display>(s: &str, n: T, min: Option<T>, max: Option<T>) -> Result<T, String> {
+  match (min, max) {
+    (Some(min), _) if n < min => Err(format!("{} is less than the minimum of {}", s, min)),
+    (_, Some(max)) if n > max => Err(format!("{} is more than the maximum of {}", s, max)),
+    _ => Ok(n),
+  }
+}
+
+fn parse_dimension(s: &str) -> Result<u32, String> {
+  match s.trim().parse::<u32>() {
+    Ok(n) if n > 0 => Ok(n),
+    _ => Err(format!("{} is not a positive integer in range", s)),
+  }
+}
+
+/// Parse a percentage, with or without a trailing `%`.
+fn parse_percent(s: &str) -> Result<f64, String> {
+  let trimmed = s.trim();
+  let number = trimmed.strip_suffix('%').unwrap_or(trimmed);
+  match number.parse::<f64>() {
+    Ok(p) if p.is_finite() => Ok(p),
+    _ => Err(format!("{} is not a percentage", s)),
+  }
+}
+
+fn parse_point(s: &str) -> Result<(f64, f64), String> {
+  let (x, y) = s.split_once(',').ok_or_else(|| format!("{} is not a point like 25%,50%", s))?;
+  Ok((parse_percent(x)?, parse_percent(y)?))
+}
+
+impl Param {
+  /// Start describing a parameter. It's optional, with no default, no abbreviation, and no help text.
+  pub fn new(name: &'static str, kind: Kind) -> Param {
+    Param { name, short: None, help: "", kind, default: None, required: false }
+  }
+
+  /// Set the single-character abbreviation.
+  pub fn short(mut self, short: char) -> Param {
+    self.short = Some(short);
+    self
+  }
+
+  /// Set the help text.
+  pub fn help(mut self, help: &'static str) -> Param {
+    self.help = help;
+    self
+  }
+
+  /// Set the default value, in text form. It's parsed like any other value, so it'd better be valid.
+  pub fn default(mut self, default: &'static str) -> Param {
+    self.default = Some(default);
+    self
+  }
+
+  /// Require a value to be given.
+  pub fn required(mut self) -> Param {
+    self.required = true;
+    self
+  }
+
+  /// Parse and validate a value for this parameter from its text form.
+  pub fn parse(&self, s: &str) -> Result<Value, String> {
+    match &self.kind {
+      Kind::Int { min, max } => {
+        let n = s.trim().parse::<i64>().map_err(|_| format!("{} is not an integer", s))?;
+        parse_bound(s, n, *min, *max).map(Value::Int)
+      }
+      Kind::Float { min, max } => {
+        let n = s.trim().parse::<f64>().map_err(|_| format!("{} is not a number", s))?;
+        if !n.is_finite() {
+          return Err(format!("{} is not a finite number", s));
+        }
+        parse_bound(s, n, *min, *max).map(Value::Float)
+      }
+      Kind::Percent { min, max } => parse_percent(s).and_then(|p| parse_bound(s, p, *min, *max)).map(Value::Percent),
+      Kind::Enum(choices) => choices.iter()
+        .find(|choice| **choice == s)
+        .map(|choice| Value::Enum(choice))
+        .ok_or_else(|| format!("{} is not one of {}", s, choices.join(", "))),
+      Kind::Path => {
+        if s.is_empty() {
+          Err("the path is empty".to_string())
+        } else {
+          Ok(Value::Path(PathBuf::from(s)))
+        }
+      }
+      Kind::Flag => match s {
+        "true" | "yes" | "on" | "1" => Ok(Value::Flag(true)),
+        "false" | "no" | "off" | "0" => Ok(Value::Flag(false)),
+        _ => Err(format!("{} is not true or false", s)),
+      },
+      Kind::Size => {
+        let mut parts = s.splitn(2, ['x', 'X', ',']);
+        match (parts.next(), parts.next()) {
+          (Some(width), Some(height)) => Ok(Value::Size(parse_dimension(width)?, parse_dimension(height)?)),
+          _ => Err(format!("{} is not a size like 1920x1080", s)),
+        }
+      }
+      Kind::Point => parse_point(s).map(|(x, y)| Value::Point(x, y)),
+      Kind::Points => s.split(';').map(parse_point).collect::<Result<_, _>>().map(Value::Points),
+    }
+  }
+}
+
+/// A complete, validated set of parameters for a generator.
+///
+/// The typed getters panic if the parameter doesn't exist or has a different type: that's a mismatch between a
+/// generator's schema and its code, not something a user can cause.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Params {
+  values: Vec<(&'static str, Value)>,
+}
+
+impl Params {
+  /// Get a parameter's value, if it has one.
+  pub fn get(&self, name: &str) -> Option<&Value> {
+    self.values.iter().find(|(n, _)| *n == name).map(|(_, v)| v)
+  }
+
+  fn expect(&self, name: &str) -> &Value {
+    self.get(name).unwrap_or_else(|| panic!("parameter {} has no value", name))
+  }
+
+  pub fn int(&self, name: &str) -> i64 {
+    match self.expect(name) {
+      Value::Int(i) => *i,
+      other => panic!("parameter {} is {:?}, not an int", name, other),
+    }
+  }
+
+  pub fn float(&self, name: &str) -> f64 {
+    match self.expect(name) {
+      Value::Float(n) => *n,
+      other => panic!("parameter {} is {:?}, not a float", name, other),
+    }
+  }
+
+  /// Get a percentage as a fraction, e.g. 25% is 0.25.
+  pub fn percent(&self, name: &str) -> f64 {
+    match self.expect(name) {
+      Value::Percent(p) => p / 100.0,
+      other => panic!("parameter {} is {:?}, not a percentage", name, other),
+    }
+  }
+
+  pub fn choice(&self, name: &str) -> &'static str {
+    match self.expect(name) {
+      Value::Enum(choice) => choice,
+      other => panic!("parameter {} is {:?}, not a choice", name, other),
+    }
+  }
+
+  pub fn path(&self, name: &str) -> &Path {
+    match self.expect(name) {
+      Value::Path(path) => path,
+      other => panic!("parameter {} is {:?}, not a path", name, other),
+    }
+  }
+
+  /// Whether a flag is set. Flags always have a value, so this never panics for a declared flag.
+  pub fn flag(&self, name: &str) -> bool {
+    match self.expect(name) {
+      Value::Flag(on) => *on,
+      other => panic!("parameter {} is {:?}, not a flag", name, other),
+    }
+  }
+
+  pub fn size(&self, name: &str) -> (u32, u32) {
+    match self.expect(name) {
+      Value::Size(width, height) => (*width, *height),
+      other => panic!("parameter {} is {:?}, not a size", name, other),
+    }
+  }
+
+  /// Get a point as fractions of the way across and down, e.g. 25%,50% is (0.25, 0.5).
+  pub fn point(&self, name: &str) -> (f64, f64) {
+    match self.expect(name) {
+      Value::Point(x, y) => (x / 100.0, y / 100.0),
+      other => panic!("parameter {} is {:?}, not a point", name, other),
+    }
+  }
+
+  /// Get a list of points as fractions, like `point`.
+  pub fn points(&self, name: &str) -> Vec<(f64, f64)> {
+    match self.expect(name) {
+      Value::Points(points) => points.iter().map(|(x, y)| (x / 100.0, y / 100.0)).collect(),
+      other => panic!("parameter {} is {:?}, not a list of points", name, other),
+    }
+  }
+
+  /// Every parameter that has a value, in schema order.
+  pub fn iter(&self) -> impl Iterator<Item = (&'static str, &Value)> {
+    self.values.iter().map(|(name, value)| (*name, value))
+  }
+
+  /// Every parameter that has a value, in text form, ready to be passed back into [`resolve`].
+  pub fn to_text(&self) -> Vec<(String, String)> {
+    self.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+  }
+}
+
+/// Check the given `(name, text)` pairs against a schema, and fill in defaults, to build a complete set of
+/// parameters. Later pairs override earlier ones with the same name, so layered sources can just be chained.
+pub fn resolve<N, V>(schema: &[Param], given: impl IntoIterator<Item = (N, V)>) -> Result<Params, String>
+where
+  N: AsRef<str>,
+  V: AsRef<str>,
+{
+  let mut raw: Vec<(&Param, String)> = vec![];
+  for (name, value) in given {
+    let name = name.as_ref();
+    let param = schema.iter()
+      .find(|p| p.name == name)
+      .ok_or_else(|| format!("there's no parameter named {}", name))?;
+    raw.retain(|(p, _)| p.name != name);
+    raw.push((param, value.as_ref().to_string()));
+  }
+
+  let mut values = Vec::with_capacity(schema.len());
+  for param in schema {
+    let text = raw.iter()
+      .find(|(p, _)| p.name == param.name)
+      .map(|(_, text)| text.as_str())
+      .or(param.default);
+    let value = match (text, &param.kind) {
+      (Some(text), _) => param.parse(text).map_err(|e| format!("{}: {}", param.name, e))?,
+      (None, Kind::Flag) => Value::Flag(false),
+      (None, _) if param.required => return Err(format!("{} is required", param.name)),
+      (None, _) => continue,
+    };
+    values.push((param.name, value));
+  }
+  Ok(Params { values })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn schema() -> Vec<Param> {
+    vec![
+      Param::new("count", Kind::Int { min: Some(1), max: Some(10) }).default("3"),
+      Param::new("zoom", Kind::Float { min: None, max: None }),
+      Param::new("start", Kind::Percent { min: Some(0.0), max: Some(100.0) }).default("10%"),
+      Param::new("mode", Kind::Enum(&["fast", "slow"])).default("fast"),
+      Param::new("input", Kind::Path).required(),
+      Param::new("sharp", Kind::Flag),
+      Param::new("size", Kind::Size).default("64x32"),
+      Param::new("center", Kind::Point).default("50%,25%"),
+      Param::new("path", Kind::Points),
+    ]
+  }
+
+  #[test]
+  fn fills_defaults() {
+    let params = resolve(&schema(), vec![("input", "a.png")]).unwrap();
+    assert_eq!(params.int("count"), 3);
+    assert_eq!(params.get("zoom"), None);
+    assert_eq!(params.percent("start"), 0.1);
+    assert_eq!(params.choice("mode"), "fast");
+    assert_eq!(params.path("input"), Path::new("a.png"));
+    assert!(!params.flag("sharp"));
+    assert_eq!(params.size("size"), (64, 32));
+    assert_eq!(params.point("center"), (0.5, 0.25));
+    assert_eq!(params.get("path"), None);
+  }
+
+  #[test]
+  fn later_values_win() {
+    let params = resolve(&schema(), vec![("input", "a.png"), ("count", "4"), ("count", "5")]).unwrap();
+    assert_eq!(params.int("count"), 5);
+  }
+
+  #[test]
+  fn rejects_bad_values() {
+    let bad = [
+      ("count", "0"), ("count", "11"), ("count", "1.5"),
+      ("zoom", "lots"), ("zoom", "inf"),
+      ("start", "10%%"), ("start", "-40%"), ("start", "250"),
+      ("mode", "medium"),
+      ("sharp", "maybe"),
+      ("size", "64"), ("size", "0x10"), ("size", "-1x10"),
+      ("center", "50%"), ("center", "a,b"),
+      ("path", ""), ("path", "10,20;"), ("path", "10,20;30"),
+      ("nonexistent", "1"),
+    ];
+    for (name, value) in bad.iter() {
+      assert!(resolve(&schema(), vec![("input", "a.png"), (name, value)]).is_err(), "{}={} was accepted", name, value);
+    }
+    assert!(resolve(&schema(), Vec::<(&str, &str)>::new()).is_err(), "missing required parameter was accepted");
+  }
+
+  #[test]
+  fn text_round_trips() {
+    let given = vec![
+      ("input", "some dir/a.png"), ("count", "7"), ("zoom", "0.1"), ("start", "2.5"),
+      ("mode", "slow"), ("sharp", "yes"), ("size", "1920,1080"), ("center", "10, 20%"), ("path", "0,0;100%,50"),
+    ];
+    let params = resolve(&schema(), given).unwrap();
+    let again = resolve(&schema(), params.to_text()).unwrap();
+    assert_eq!(params, again);
+    assert_eq!(again.float("zoom"), 0.1);
+    assert_eq!(again.percent("start"), 0.025);
+    assert_eq!(again.points("path"), [(0.0, 0.0), (1.0, 0.5)]);
+  }
+}
@@ -0,0 +1,296 @@
+//! Recording how an image was made inside the image itself, so it can be made again later.
+//! The recipe is stored as PNG text chunks, which every PNG reader ignores unless it cares about them.
+
+use crate::{
+  seed,
+  utils::render::{Tile, View},
+};
+
+/// The PNG file signature, which every PNG starts with.
+pub(crate) const PNG_SIG: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+const KEY_SOFTWARE: &str = "Software";
+const KEY_VERSION: &str = "hart-version";
+const KEY_GENERATOR: &str = "hart-generator";
+const KEY_PARAMS: &str = "hart-params";
+const KEY_SEED: &str = "hart-seed";
+const KEY_VIEW: &str = "hart-view";
+
+/// Everything needed to run a generator again and get the same output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recipe {
+  /// The version of hart that produced the output
+  pub version: String,
+  /// The generator's category and command, joined with a `/`, e.g. `basic/worley`
+  pub generator: String,
+  /// Every one of the generator's parameters, as `(name, value)` in text form, defaults included
+  pub params: Vec<(String, String)>,
+  /// The raw seed bytes
+  pub seed: Vec<u8>,
+  /// The part of the generator's output that was rendered, if it wasn't just the whole thing at full size
+  pub view: Option<View>,
+}
+
+/// Write a view as `x,y,width,height@scale`.
+fn join_view(view: &View) -> String {
+  let View { area, scale } = view;
+  format!("{},{},{},{}@{}", area.x, area.y, area.width, area.height, scale)
+}
+
+fn split_view(text: &str) -> Result<View, String> {
+  let bad = || format!("malformed view {}", text);
+  let (area, scale) = text.split_once('@').ok_or_else(bad)?;
+  let nums = area.split(',').map(|n| n.parse::<u32>().map_err(|_| bad())).collect::<Result<Vec<_>, _>>()?;
+  match nums.as_slice() {
+    &[x, y, width, height] => Ok(View {
+      area: Tile { x, y, width, height },
+      scale: scale.parse().map_err(|_| bad())?,
+    }),
+    _ => Err(bad()),
+  }
+}
+
+/// Escape params so they can all be stored in one text chunk, one `name=value` per line.
+fn join_params(params: &[(String, String)]) -> String {
+  params.iter()
+    .map(|(name, value)| format!("{}={}", name, value).replace('\\', "\\\\").replace('\n', "\\n"))
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+fn split_params(joined: &str) -> Result<Vec<(String, String)>, String> {
+  if joined.is_empty() {
+    return Ok(vec![]);
+  }
+  joined.split('\n')
+    .map(|line| {
+      let mut out = String::with_capacity(line.len());
+      let mut chars = line.chars();
+      while let Some(c) = chars.next() {
+        if c != '\\' {
+          out.push(c);
+          continue;
+        }
+        match chars.next() {
+          Some('n') => out.push('\n'),
+          Some(other) => out.push(other),
+          None => out.push('\\'),
+        }
+      }
+      match out.split_once('=') {
+        Some((name, value)) => Ok((name.to_string(), value.to_string())),
+        None => Err(format!("malformed parameter {}", out)),
+      }
+    })
+    .collect()
+}
+
+/// Whether some data is (or at least, claims to be) an encoded PNG.
+pub fn is_png(data: &[u8]) -> bool {
+  data.starts_with(PNG_SIG)
+}
+
+/// Build a complete PNG chunk, length and CRC included.
+pub(crate) fn chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+  let mut out = Vec::with_capacity(data.len() + 12);
+  out.extend(&(data.len() as u32).to_be_bytes());
+  out.extend(kind);
+  out.extend(data);
+  let mut crc = crc32fast::Hasher::new();
+  crc.update(kind);
+  crc.update(data);
+  out.extend(&crc.finalize().to_be_bytes());
+  out
+}
+
+/// Build a text chunk: tEXt if the text is plain ASCII (so Latin-1 readers agree with us), iTXt otherwise.
+fn text_chunk(key: &str, value: &str) -> Vec<u8> {
+  let mut data = key.as_bytes().to_vec();
+  data.push(0);
+  if value.bytes().all(|b| b.is_ascii() && b != 0) {
+    data.extend(value.as_bytes());
+    chunk(b"tEXt", &data)
+  } else {
+    // uncompressed, no language tag, no translated keyword
+    data.extend(&[0, 0, 0, 0]);
+    data.extend(value.as_bytes());
+    chunk(b"iTXt", &data)
+  }
+}
+
+/// A PNG chunk's type and data.
+type Chunk<'a> = ([u8; 4], &'a [u8]);
+
+/// Split a PNG into its chunks.
+fn chunks(png: &[u8]) -> Result<Vec<Chunk<'_>>, String> {
+  if !png.starts_with(PNG_SIG) {
+    return Err("not a PNG file".to_string());
+  }
+  let mut res = vec![];
+  let mut pos = PNG_SIG.len();
+  while pos < png.len() {
+    if png.len() - pos < 12 {
+      return Err("truncated PNG chunk".to_string());
+    }
+    let mut len = [0; 4];
+    len.copy_from_slice(&png[pos..pos + 4]);
+    let len = u32::from_be_bytes(len) as usize;
+    let mut kind = [0; 4];
+    kind.copy_from_slice(&png[pos + 4..pos + 8]);
+    let start = pos + 8;
+    if png.len() - start < len + 4 {
+      return Err("truncated PNG chunk".to_string());
+    }
+    res.push((kind, &png[start..start + len]));
+    pos = start + len + 4;
+  }
+  Ok(res)
+}
+
+/// Parse a tEXt or iTXt chunk's contents into a key and value. Compressed text is skipped, since hart never
+/// writes it.
+fn parse_text(kind: &[u8; 4], data: &[u8]) -> Option<(String, String)> {
+  let split = data.iter().position(|&b| b == 0)?;
+  let key = String::from_utf8_lossy(&data[..split]).into_owned();
+  let rest = &data[split + 1..];
+  match kind {
+    // tEXt is Latin-1, which maps directly onto the first 256 code points
+    b"tEXt" => Some((key, rest.iter().map(|&b| b as char).collect())),
+    b"iTXt" => {
+      let (&compressed, rest) = rest.split_first()?;
+      if compressed != 0 {
+        return None;
+      }
+      // skip the compression method, then the language tag and translated keyword
+      let rest = rest.get(1..)?;
+      let lang_end = rest.iter().position(|&b| b == 0)?;
+      let rest = &rest[lang_end + 1..];
+      let trans_end = rest.iter().position(|&b| b == 0)?;
+      let text = String::from_utf8(rest[trans_end + 1..].to_vec()).ok()?;
+      Some((key, text))
+    }
+    _ => None,
+  }
+}
+
+impl Recipe {
+  /// The key/value pairs this recipe is stored as.
+  pub fn to_text(&self) -> Vec<(&'static str, String)> {
+    let mut text = vec![
+      (KEY_SOFTWARE, format!("hart {}", self.version)),
+      (KEY_VERSION, self.version.clone()),
+      (KEY_GENERATOR, self.generator.clone()),
+      (KEY_PARAMS, join_params(&self.params)),
+      (KEY_SEED, seed::to_hex(&self.seed)),
+    ];
+    if let Some(view) = &self.view {
+      text.push((KEY_VIEW, join_view(view)));
+    }
+    text
+  }
+
+  /// This recipe as encoded PNG text chunks, ready to go anywhere between the header and the image data.
+  pub fn png_chunks(&self) -> Vec<u8> {
+    self.to_text().into_iter().flat_map(|(key, value)| text_chunk(key, &value)).collect()
+  }
+
+  /// Insert this recipe into an encoded PNG, as text chunks right after the header.
+  pub fn embed(&self, png: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(png.len() + 1024);
+    out.extend(PNG_SIG);
+    for (kind, data) in chunks(png)? {
+      out.extend(chunk(&kind, data));
+      if &kind == b"IHDR" {
+        out.extend(self.png_chunks());
+      }
+    }
+    Ok(out)
+  }
+
+  /// Read a recipe back out of an encoded PNG.
+  pub fn extract(png: &[u8]) -> Result<Recipe, String> {
+    let mut version = None;
+    let mut generator = None;
+    let mut params = None;
+    let mut seed = None;
+    let mut view = None;
+    for (kind, data) in chunks(png)? {
+      match parse_text(&kind, data) {
+        Some((key, value)) if key == KEY_VERSION => version = Some(value),
+        Some((key, value)) if key == KEY_GENERATOR => generator = Some(value),
+        Some((key, value)) if key == KEY_PARAMS => params = Some(split_params(&value)?),
+        Some((key, value)) if key == KEY_SEED => seed = Some(seed::from_hex(&value)?),
+        Some((key, value)) if key == KEY_VIEW => view = Some(split_view(&value)?),
+        _ => (),
+      }
+    }
+    let missing = |key| format!("no {} in the image; it wasn't made by hart, or has been re-saved since", key);
+    Ok(Recipe {
+      version: version.ok_or_else(|| missing(KEY_VERSION))?,
+      generator: generator.ok_or_else(|| missing(KEY_GENERATOR))?,
+      params: params.ok_or_else(|| missing(KEY_PARAMS))?,
+      seed: seed.ok_or_else(|| missing(KEY_SEED))?,
+      view,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    image::{codecs::png::PngEncoder, ColorType},
+  };
+
+  fn tiny_png() -> Vec<u8> {
+    let mut png = vec![];
+    PngEncoder::new(&mut png).encode(&[0, 64, 128, 255], 2, 2, ColorType::L8).unwrap();
+    png
+  }
+
+  fn recipe() -> Recipe {
+    Recipe {
+      version: "1.2.3".to_string(),
+      generator: "basic/worley".to_string(),
+      params: vec![
+        ("size".into(), "30x20".into()),
+        ("path".into(), "line\nbreak \\n=".into()),
+        ("unicode".into(), "ünïcödé".into()),
+      ],
+      seed: vec![0, 1, 2, 255],
+      view: None,
+    }
+  }
+
+  #[test]
+  fn round_trips() {
+    let embedded = recipe().embed(&tiny_png()).unwrap();
+    assert_eq!(Recipe::extract(&embedded).unwrap(), recipe());
+  }
+
+  #[test]
+  fn embedded_png_still_decodes() {
+    let embedded = recipe().embed(&tiny_png()).unwrap();
+    let img = image::load_from_memory(&embedded).unwrap();
+    assert_eq!(img.to_luma8().into_raw(), vec![0, 64, 128, 255]);
+  }
+
+  #[test]
+  fn no_params_round_trips() {
+    let recipe = Recipe { params: vec![], ..recipe() };
+    assert_eq!(Recipe::extract(&recipe.embed(&tiny_png()).unwrap()).unwrap(), recipe);
+  }
+
+  #[test]
+  fn views_round_trip() {
+    let view = View { area: Tile { x: 5, y: 6, width: 70, height: 80 }, scale: 0.123_456_79 };
+    let recipe = Recipe { view: Some(view), ..recipe() };
+    assert_eq!(Recipe::extract(&recipe.embed(&tiny_png()).unwrap()).unwrap(), recipe);
+  }
+
+  #[test]
+  fn missing_recipe_is_an_error() {
+    assert!(Recipe::extract(&tiny_png()).is_err());
+    assert!(Recipe::extract(b"not a png").is_err());
+  }
+}
@@ -0,0 +1,587 @@
+//! Running generators and writing what they make: everything the `hart` binary does once it's worked out what it's
+//! been asked to do, for other programs to drive hart the same way.
+//!
+//! Failures carry an [`Exit`] code from BSD's sysexits.h, so scripts can tell them apart. Output is written through a
+//! [`Sink`], so a failure never leaves a half-written file behind.
+
+use {
+  crate::{
+    batch::Variant,
+    gens::{Context as _, Gen, GenFail, Registry, STDIO},
+    jobs,
+    output::{self, Encoding, Output},
+    params::{self, Kind, Param, Params},
+    recipe::Recipe,
+    sheet,
+    sink::Sink,
+    utils::{
+      render::{Progress, Raster, Renderer, View},
+      Span,
+    },
+    VERSION,
+  },
+  rayon::iter::{IntoParallelIterator as _, ParallelIterator as _},
+  std::{
+    error,
+    fmt,
+    fs,
+    io::{self, IsTerminal as _, Read as _, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, SystemTime},
+  },
+};
+
+/// The size of each tile from `tiles`; 256 is what every slippy map viewer expects.
+pub const TILE_SIZE: u32 = 256;
+
+/// The deepest zoom level `tiles` will go to, at which point there are about a trillion tiles.
+pub const MAX_ZOOM: u32 = 20;
+
+/// How often `watch` checks whether anything it's watching has changed.
+const POLL: Duration = Duration::from_millis(250);
+
+/// How long things `watch` is watching have to stay the same before it runs again, so saving several files at once,
+/// or an editor writing one in several steps, only sets off one run.
+const DEBOUNCE: Duration = Duration::from_millis(400);
+
+/// Whether the progress bar is on screen, and has to be cleared before anything else is written to stderr.
+static BAR_SHOWN: AtomicBool = AtomicBool::new(false);
+
+/// Logs to stderr, so stdout only ever has output on it. Info messages are written bare, since they're the normal
+/// chatter; everything else says what level it is.
+struct Logger;
+
+impl log::Log for Logger {
+  fn enabled(&self, metadata: &log::Metadata) -> bool {
+    metadata.level() <= log::max_level()
+  }
+
+  fn log(&self, record: &log::Record) {
+    if !self.enabled(record.metadata()) {
+      return;
+    }
+    let mut stderr = io::stderr().lock();
+    if BAR_SHOWN.swap(false, Ordering::Relaxed) {
+      let _ = write!(stderr, "\r\x1b[K");
+    }
+    let _ = match record.level() {
+      log::Level::Info => writeln!(stderr, "{}", record.args()),
+      level => writeln!(stderr, "{}: {}", level.as_str().to_lowercase(), record.args()),
+    };
+  }
+
+  fn flush(&self) {}
+}
+
+/// Log to stderr at `level`, in a way that doesn't garble the progress bar. This can only be done once.
+pub fn init_logging(level: log::LevelFilter) {
+  log::set_logger(&Logger).expect("logging was already set up");
+  log::set_max_level(level);
+}
+
+/// Run `f`, drawing a bar on stderr showing how far along rendering is while it does, if stderr is a terminal that'd
+/// show it and we haven't been asked to be quiet.
+pub fn with_progress<T>(f: impl FnOnce() -> T) -> T {
+  if log::max_level() < log::LevelFilter::Info || !io::stderr().is_terminal() {
+    return f();
+  }
+  const WIDTH: u64 = 40;
+  let finished = AtomicBool::new(false);
+  thread::scope(|s| {
+    s.spawn(|| {
+      while !finished.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_millis(100));
+        let (done, total) = Progress::global().get();
+        if total == 0 || done >= total {
+          continue;
+        }
+        let filled = done * WIDTH / total;
+        let bar: String = (0..WIDTH).map(|i| if i < filled { '#' } else { '-' }).collect();
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r[{}] {:>3}%\x1b[K", bar, done * 100 / total);
+        let _ = stderr.flush();
+        BAR_SHOWN.store(true, Ordering::Relaxed);
+      }
+      if BAR_SHOWN.swap(false, Ordering::Relaxed) {
+        let _ = write!(io::stderr().lock(), "\r\x1b[K");
+      }
+    });
+    let result = f();
+    finished.store(true, Ordering::Relaxed);
+    result
+  })
+}
+
+/// How hart can fail, with exit codes from BSD's sysexits.h so scripts can tell failures apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+  /// The command line didn't make sense
+  Usage = 64,
+  /// An input was there, but wasn't valid, like an image that won't decode or a broken recipe
+  Data = 65,
+  /// An input couldn't be opened
+  NoInput = 66,
+  /// A plugin misbehaved, which is a bug in the plugin rather than anything the user did
+  Software = 70,
+  /// An output couldn't be created
+  CantCreate = 73,
+  /// Reading or writing failed partway through
+  Io = 74,
+}
+
+/// A failure, and the exit code it should cause.
+#[derive(Debug)]
+pub struct Failure {
+  pub exit: Exit,
+  pub error: GenFail,
+}
+
+impl From<GenFail> for Failure {
+  /// Work out the exit code from the kind of failure, for when the code failing doesn't know any better.
+  fn from(error: GenFail) -> Failure {
+    Failure { exit: Exit::of(&error), error }
+  }
+}
+
+impl Exit {
+  /// The exit code for a kind of failure. Context only says what was going on at the time, so it's the failure
+  /// underneath that counts.
+  pub fn of(error: &GenFail) -> Exit {
+    match error {
+      GenFail::Context(_, inner) => Exit::of(inner),
+      GenFail::BadArg(_) => Exit::Usage,
+      GenFail::Data(_) | GenFail::Image(_) => Exit::Data,
+      GenFail::Plugin(_) => Exit::Software,
+      GenFail::Io(e) if e.kind() == io::ErrorKind::NotFound => Exit::NoInput,
+      GenFail::Io(_) => Exit::Io,
+    }
+  }
+}
+
+impl Failure {
+  /// A failure because what was asked for doesn't make sense.
+  pub fn usage(message: String) -> Failure {
+    Failure { exit: Exit::Usage, error: GenFail::BadArg(message) }
+  }
+}
+
+/// Fail with a particular exit code, saying what was going on at the time.
+pub trait OrExit<T> {
+  fn or_exit<C: fmt::Display>(self, exit: Exit, context: impl FnOnce() -> C) -> Result<T, Failure>;
+}
+
+impl<T, E: Into<GenFail>> OrExit<T> for Result<T, E> {
+  fn or_exit<C: fmt::Display>(self, exit: Exit, context: impl FnOnce() -> C) -> Result<T, Failure> {
+    self.context(context).map_err(|error| Failure { exit, error })
+  }
+}
+
+/// Say what went wrong all the way down, like "couldn't open a.png: No such file or directory".
+pub fn describe(error: &GenFail) -> String {
+  let mut message = error.to_string();
+  let mut source = error::Error::source(error);
+  while let Some(cause) = source {
+    message = format!("{}: {}", message, cause);
+    source = cause.source();
+  }
+  message
+}
+
+/// Get a generator's raster, for when it has to be rendered in pieces or only in part.
+pub fn raster(gen: &dyn Gen, params: &Params, recipe: &Recipe, why: &str) -> crate::gens::Result<Box<dyn Raster>> {
+  gen.raster(params, &recipe.seed)?.ok_or_else(|| {
+    GenFail::BadArg(format!("{} can only make its whole output at once, so it can't {}", recipe.generator, why))
+  })
+}
+
+/// Make sure a generator can be rendered on a given canvas, which is either the generator's own size, or anything at
+/// all for generators which are defined everywhere.
+fn check_canvas(raster: &dyn Raster, generator: &str, canvas: Option<(u32, u32)>) -> crate::gens::Result<(u32, u32)> {
+  match canvas {
+    Some(canvas) if canvas != raster.dimensions() && !raster.unbounded() => Err(GenFail::BadArg(format!(
+      "{} only covers {:?}, so it can't be rendered on a different canvas", generator, raster.dimensions(),
+    ))),
+    Some(canvas) => Ok(canvas),
+    None => Ok(raster.dimensions()),
+  }
+}
+
+/// Make sure nothing else is competing for stdin if an input is going to be read from it. Only one thing can be, since
+/// it can only be read once.
+pub fn check_stdin(schema: &[Param], params: &Params, seed_from_stdin: bool) -> Result<(), String> {
+  let inputs = schema.iter()
+    .filter(|param| param.kind == Kind::Path && params.path(param.name) == Path::new(STDIO))
+    .count();
+  match (inputs, seed_from_stdin) {
+    (0, _) | (1, false) => Ok(()),
+    (1, true) => Err("an input is being read from stdin, so the seed has to be given some other way".to_string()),
+    _ => Err("only one input can be read from stdin".to_string()),
+  }
+}
+
+/// Run a generator and write its output, with the recipe embedded if the format allows.
+/// If the recipe has a view, only that part of the output is rendered, and it has to fit in `canvas` if that's given.
+/// If `stream` is set, the image is rendered and written a piece at a time instead of all at once.
+pub fn generate(
+  gen: &dyn Gen, params: &Params, recipe: &Recipe, encoding: &Encoding, canvas: Option<(u32, u32)>,
+  stream: bool, output_path: &str,
+) -> Result<(), Failure> {
+  if stream || recipe.view.is_some() {
+    let raster = raster(gen, params, recipe, if stream { "be streamed" } else { "render just a region" })?;
+    let (width, height) = raster.dimensions();
+    let view = recipe.view.unwrap_or_else(|| View::whole(width, height));
+    if let Some(canvas) = canvas {
+      let (width, height) = check_canvas(&*raster, &recipe.generator, Some(canvas))?;
+      let area = view.area;
+      if area.x + area.width > width || area.y + area.height > height {
+        return Err(Failure::usage(format!(
+          "the region {},{},{},{} doesn't fit in a {}x{} canvas",
+          area.x, area.y, area.width, area.height, width, height,
+        )));
+      }
+    }
+    if !raster.covers(view) {
+      return Err(Failure::usage(format!(
+        "{} is only {}x{}, and the region is outside of that", recipe.generator, width, height,
+      )));
+    }
+    encoding.check_path(Path::new(output_path)).map_err(Failure::usage)?;
+    let mut sink = Sink::create(Path::new(output_path))?;
+    let written = if stream {
+      output::stream(&*raster, view, Some(recipe), encoding, &mut sink)
+    } else {
+      let out = raster.render(&Renderer::new(), view).into();
+      output::write(&out, Some(recipe), encoding, &mut sink)
+    };
+    written.context(|| format!("couldn't write {}", sink.name()))?;
+    sink.finish()
+  } else {
+    let out = gen.run(params, &recipe.seed)?;
+    if let Output::Image(_) = out {
+      encoding.check_path(Path::new(output_path)).map_err(Failure::usage)?;
+      if !encoding.format.holds_recipe() {
+        log::warn!("{} can't hold a recipe, so `hart reproduce` won't work on {}", encoding.format.name(), output_path);
+      }
+    }
+    let mut sink = Sink::create(Path::new(output_path))?;
+    output::write(&out, Some(recipe), encoding, &mut sink).context(|| format!("couldn't write {}", sink.name()))?;
+    sink.finish()
+  }
+}
+
+/// Write a slippy map pyramid of a generator's output into a directory, as `zoom/x/y.png` (or whatever format was
+/// asked for), from zoom level 0 up to `max_zoom`. Every tile carries its own recipe.
+pub fn tiles(
+  gen: &dyn Gen, params: &Params, recipe: &Recipe, encoding: &Encoding, canvas: Option<(u32, u32)>,
+  max_zoom: u32, dir: &str,
+) -> Result<(), Failure> {
+  if dir == STDIO {
+    return Err(Failure::usage("tiles are written to a directory, so they can't go to stdout".to_string()));
+  }
+  let raster = raster(gen, params, recipe, "be tiled")?;
+  if !raster.unbounded() {
+    return Err(Failure::usage(format!("{} has edges, so it can't be tiled", recipe.generator)));
+  }
+  let (width, height) = check_canvas(&*raster, &recipe.generator, canvas)?;
+  let renderer = Renderer::new().quiet();
+  let _span = Span::enter("render tiles");
+  let tile_count = |zoom| {
+    let (across, down) = View::slippy_count(width, height, zoom);
+    across as u64 * down as u64
+  };
+  let total: u64 = (0..=max_zoom).map(tile_count).sum();
+  Progress::global().start(total * TILE_SIZE as u64 * TILE_SIZE as u64);
+  for zoom in 0..=max_zoom {
+    let (across, down) = View::slippy_count(width, height, zoom);
+    for x in 0..across {
+      let column = Path::new(dir).join(zoom.to_string()).join(x.to_string());
+      fs::create_dir_all(&column).or_exit(Exit::CantCreate, || format!("couldn't create {}", column.display()))?;
+      for y in 0..down {
+        let view = View::slippy(width, height, TILE_SIZE, zoom, x, y);
+        let recipe = Recipe { view: Some(view), ..recipe.clone() };
+        let out = raster.render(&renderer, view).into();
+        let mut sink = Sink::create(&column.join(format!("{}.{}", y, encoding.format.name())))?;
+        output::write(&out, Some(&recipe), encoding, &mut sink).context(|| format!("couldn't write {}", sink.name()))?;
+        sink.finish()?;
+      }
+    }
+  }
+  log::info!("Wrote {} tiles to {}", total, dir);
+  Ok(())
+}
+
+/// The recipe for the same render at 1/`factor` of the size. It samples the same points the full render would, so
+/// it's a faithful preview rather than a different image, and reproducing it makes the same preview again.
+pub fn preview(gen: &dyn Gen, params: &Params, recipe: Recipe, factor: u32) -> crate::gens::Result<Recipe> {
+  let raster = raster(gen, params, &recipe, "be previewed")?;
+  let (width, height) = raster.dimensions();
+  let view = recipe.view.unwrap_or_else(|| View::whole(width, height)).shrink(factor);
+  Ok(Recipe { view: Some(view), ..recipe })
+}
+
+/// Run a generator and show its output in the terminal, rather than writing it anywhere.
+pub fn show(gen: &dyn Gen, params: &Params, recipe: &Recipe) -> Result<(), Failure> {
+  let out = match recipe.view {
+    Some(view) => {
+      let raster = raster(gen, params, recipe, "render just a region")?;
+      if !raster.covers(view) {
+        let (width, height) = raster.dimensions();
+        return Err(Failure::usage(format!(
+          "{} is only {}x{}, and the region is outside of that", recipe.generator, width, height,
+        )));
+      }
+      raster.render(&Renderer::new(), view).into()
+    }
+    None => gen.run(params, &recipe.seed)?,
+  };
+  let mut sink = Sink::create(Path::new(STDIO))?;
+  output::terminal(&out, &mut sink).context(|| "couldn't write to stdout")?;
+  sink.finish()
+}
+
+/// Write lines of text to stdout.
+pub fn print(lines: &[String]) -> Result<(), Failure> {
+  let mut sink = Sink::create(Path::new(STDIO))?;
+  for line in lines {
+    writeln!(sink, "{}", line).or_exit(Exit::Io, || "couldn't write to stdout")?;
+  }
+  sink.finish()
+}
+
+/// Where `reproduce` writes to by default: `some/image.png` becomes `some/image-reproduced.png`.
+pub fn reproduced_path(path: &str) -> String {
+  let path = Path::new(path);
+  let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+  let name = match path.extension() {
+    Some(ext) => format!("{}-reproduced.{}", stem, ext.to_string_lossy()),
+    None => format!("{}-reproduced", stem),
+  };
+  path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// Run the generator that made an image (read from stdin if `image_path` is `-`) again, with the same parameters and
+/// seed, and write the output to `output_path`. With a `preview` factor, it's rendered that much smaller.
+pub fn reproduce(
+  registry: &Registry, image_path: &str, output_path: &str, encoding: &Encoding, preview: Option<u32>, stream: bool,
+) -> Result<(), Failure> {
+  let image = if image_path == STDIO {
+    let mut image = Vec::new();
+    io::stdin().lock().read_to_end(&mut image).or_exit(Exit::Io, || "couldn't read stdin")?;
+    image
+  } else {
+    fs::read(image_path).or_exit(Exit::NoInput, || format!("couldn't read {}", image_path))?
+  };
+  let broken = |e| Failure::from(GenFail::Data(format!("{} has a broken recipe: {}", image_path, e)));
+  let recipe = Recipe::extract(&image).map_err(broken)?;
+  if recipe.version != VERSION {
+    log::warn!("{} was made by hart {}, but this is hart {}; the output may differ", image_path, recipe.version,
+      VERSION);
+  }
+  let recipe = Recipe { version: VERSION.to_string(), ..recipe };
+
+  let gen = recipe.generator.split_once('/')
+    .and_then(|(category, command)| registry.find(category, command))
+    .ok_or_else(|| broken(format!("there's no generator called {}", recipe.generator)))?;
+
+  let params = params::resolve(&gen.params(), recipe.params.iter().cloned()).map_err(broken)?;
+  let recipe = match preview {
+    Some(factor) => self::preview(gen, &params, recipe, factor)?,
+    None => recipe,
+  };
+
+  with_progress(|| generate(gen, &params, &recipe, encoding, None, stream, output_path))
+}
+
+/// One render in a batch, ready to go.
+pub struct Render {
+  pub params: Params,
+  pub recipe: Recipe,
+  pub encoding: Encoding,
+  pub output_path: String,
+}
+
+/// Render a batch of variants of a generator in parallel, each to its own output. Every output has to be different,
+/// which is checked before any are rendered, and a render failing doesn't stop the rest.
+pub fn batch(gen: &dyn Gen, renders: Vec<Render>, canvas: Option<(u32, u32)>, stream: bool) -> Result<(), Failure> {
+  let paths: Vec<_> = renders.iter().map(|render| render.output_path.clone()).collect();
+  crate::batch::check_distinct(&paths).map_err(Failure::usage)?;
+
+  let total = renders.len();
+  let _span = Span::enter("render the batch");
+  let progress = Progress::global().start_jobs(total as u64);
+  let failures: Vec<Failure> = renders.into_par_iter()
+    .filter_map(|render| {
+      let result = generate(gen, &render.params, &render.recipe, &render.encoding, canvas, stream, &render.output_path);
+      progress.finish();
+      report(&render.output_path, result)
+    })
+    .collect();
+  summarize(&failures, total, "variants")
+}
+
+/// Log how one of several renders went, since one failing doesn't stop the rest, and pass its failure on if it failed.
+fn report(output_path: &str, result: Result<(), Failure>) -> Option<Failure> {
+  match result {
+    Ok(()) => {
+      log::info!("Wrote {}", output_path);
+      None
+    }
+    Err(Failure { exit, error }) => {
+      let error = GenFail::Context(format!("couldn't make {}", output_path), Box::new(error));
+      log::error!("{}", describe(&error));
+      Some(Failure { exit, error })
+    }
+  }
+}
+
+/// Fail if any of several renders did, with the first one's exit code. Each has already been reported by `report`.
+fn summarize(failures: &[Failure], total: usize, what: &str) -> Result<(), Failure> {
+  match failures.first() {
+    Some(first) => Err(Failure {
+      exit: first.exit,
+      error: GenFail::BadArg(format!("{} of {} {} failed", failures.len(), total, what)),
+    }),
+    None => Ok(()),
+  }
+}
+
+/// Make the directory a file's going in, if it isn't there yet.
+fn make_parent(path: &Path) -> Result<(), Failure> {
+  match path.parent() {
+    Some(dir) if !dir.as_os_str().is_empty() => {
+      fs::create_dir_all(dir).or_exit(Exit::CantCreate, || format!("couldn't create {}", dir.display()))
+    }
+    _ => Ok(()),
+  }
+}
+
+/// Run every job in a job file. Jobs run at the same time unless one needs another's output, and a job failing only
+/// stops the ones which need its output. Output directories are made as needed. With a `preview` factor, every job is
+/// rendered that much smaller.
+pub fn job_file(registry: &Registry, path: &str, preview: Option<u32>) -> Result<(), Failure> {
+  let contents = fs::read_to_string(path).or_exit(Exit::NoInput, || format!("couldn't read {}", path))?;
+  let broken = |e| Failure::from(GenFail::Data(format!("{} is broken: {}", path, e)));
+  let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+  let mut jobs = jobs::parse(&contents, dir, registry).map_err(broken)?;
+  if let Some(factor) = preview {
+    for job in &mut jobs {
+      job.recipe = self::preview(job.gen, &job.params, job.recipe.clone(), factor)
+        .or_exit(Exit::Usage, || format!("couldn't preview {}", job.name))?;
+    }
+  }
+  let stages = jobs::stages(&jobs).map_err(broken)?;
+  for job in &jobs {
+    if job.gen.experimental() {
+      log::warn!("{} uses {}, which is experimental, so its output may change between versions", job.name,
+        job.recipe.generator);
+    }
+  }
+
+  with_progress(|| {
+    let _span = Span::enter("run the jobs");
+    let progress = Progress::global().start_jobs(jobs.len() as u64);
+    let mut failed = vec![false; jobs.len()];
+    let mut failures = vec![];
+    for stage in stages {
+      let results: Vec<(usize, Option<Failure>)> = stage.into_par_iter()
+        .map(|i| {
+          let job = &jobs[i];
+          let output_path = job.output.display().to_string();
+          let result = match (0..jobs.len()).find(|&f| failed[f] && job.inputs.contains(&jobs[f].output)) {
+            Some(f) => {
+              Err(Failure::usage(format!("{} needs the output of {}, which failed", job.name, jobs[f].name)))
+            }
+            None => make_parent(&job.output).and_then(|()| {
+              generate(job.gen, &job.params, &job.recipe, &job.encoding, job.canvas, job.stream, &output_path)
+            }),
+          };
+          progress.finish();
+          (i, report(&output_path, result))
+        })
+        .collect();
+      for (i, failure) in results {
+        failed[i] = failure.is_some();
+        failures.extend(failure);
+      }
+    }
+    summarize(&failures, jobs.len(), "jobs")
+  })
+}
+
+/// The files running a job file depends on: the job file itself, and every job's inputs, except for ones another job
+/// writes, since those change every run.
+pub fn job_file_inputs(registry: &Registry, path: &str) -> Vec<PathBuf> {
+  let contents = fs::read_to_string(path).unwrap_or_default();
+  let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+  // if the file's broken, there's nothing to watch but it, until it's fixed
+  let jobs = jobs::parse(&contents, dir, registry).unwrap_or_default();
+  let inputs = jobs.iter()
+    .flat_map(|job| job.inputs.iter())
+    .filter(|input| !jobs.iter().any(|job| &job.output == *input))
+    .cloned();
+  std::iter::once(PathBuf::from(path)).chain(inputs).collect()
+}
+
+/// When each file was last modified, or `None` for files that can't be looked at, like ones that don't exist yet.
+fn mtimes(paths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+  paths.iter().map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok()).collect()
+}
+
+/// Run `run`, then run it again whenever any of the files `watched` lists change, forever. What's watched is looked at
+/// again before each run, since changing one file (like the config) can change which others matter. Failures are
+/// logged, and then it keeps watching, so mistakes can be fixed without starting over.
+pub fn watch(watched: impl Fn() -> Vec<PathBuf>, run: impl Fn() -> Result<(), Failure>) -> Result<(), Failure> {
+  loop {
+    let paths = watched();
+    let mut last = mtimes(&paths);
+    if let Err(failure) = run() {
+      log::error!("{}", describe(&failure.error));
+    }
+    let names: Vec<_> = paths.iter().map(|path| path.display().to_string()).collect();
+    log::info!("Watching {} for changes", names.join(", "));
+    while mtimes(&paths) == last {
+      thread::sleep(POLL);
+    }
+    // wait for everything to settle before running again
+    loop {
+      let now = mtimes(&paths);
+      if now == last {
+        break;
+      }
+      last = now;
+      thread::sleep(DEBOUNCE);
+    }
+  }
+}
+
+/// Render a thumbnail no bigger than `size` of every variant, in parallel, lay them out in a grid captioned with each
+/// one's seed and swept parameters, and write it to `output_path`. Without a number of `columns`, the grid is about
+/// square.
+pub fn sheet(
+  gen: &dyn Gen, variants: Vec<Variant>, size: u32, columns: Option<u32>, encoding: &Encoding, output_path: &str,
+) -> Result<(), Failure> {
+  encoding.check_path(Path::new(output_path)).map_err(Failure::usage)?;
+  let columns = columns.unwrap_or_else(|| (variants.len() as f64).sqrt().ceil() as u32);
+  let cells = with_progress(|| {
+    let _span = Span::enter("render thumbnails");
+    let progress = Progress::global().start_jobs(variants.len() as u64);
+    variants.into_par_iter()
+      .map(|Variant { seed, swept, params }| {
+        let thumbnail = sheet::thumbnail(gen, &params, &seed.bytes, size)
+          .context(|| format!("couldn't render seed {}", seed.label));
+        progress.finish();
+        let caption = std::iter::once(format!("seed {}", seed.label))
+          .chain(swept.into_iter().map(|(name, value)| format!("{}={}", name, value)))
+          .collect();
+        Ok((thumbnail?, caption))
+      })
+      .collect::<Result<Vec<_>, Failure>>()
+  })?;
+  let sheet = sheet::compose(&cells, columns);
+
+  let mut sink = Sink::create(Path::new(output_path))?;
+  output::write(&sheet.into(), None, encoding, &mut sink).context(|| format!("couldn't write {}", sink.name()))?;
+  sink.finish()
+}
@@ -0,0 +1,261 @@
+//! Descriptions of generators and their parameters: as JSON, so other programs (like web frontends) can build forms
+//! for them and check values before running anything, and as text, for people.
+//!
+//! A generator is described as an object with its `category`, `command`, full `name` (like `basic/worley`), `about`,
+//! `aliases`, `tags`, whether it's `hidden` or `experimental`, and its `params`. Each parameter has its `name`,
+//! `short` option (or null), `help`, `kind`, `default` (or null), and whether it's `required`. Some kinds say more:
+//! `int`, `float`, and `percent` have `min` and `max` (either of which can be null), and `enum` has its `choices`.
+//! Defaults are JSON values of the parameter's type: numbers for `int`, `float`, and `percent`, booleans for `flag`
+//! (which default to false), `[w, h]` for `size`, `[x, y]` percentages for `point`, a list of those for `points`, and
+//! strings for the rest.
+
+use {
+  crate::{
+    gens::{Category, Gen, Registry},
+    params::{Kind, Param, Value},
+  },
+  std::fmt,
+};
+
+/// What's said about experimental generators when describing them.
+pub const EXPERIMENTAL: &str = "This generator is experimental, so its output may change between versions of hart, \
+  and `hart reproduce` may not reproduce it exactly.";
+
+/// Write a string as a JSON string, quoted and escaped.
+pub fn string(s: &str) -> String {
+  let mut out = String::with_capacity(s.len() + 2);
+  out.push('"');
+  for c in s.chars() {
+    match c {
+      '"' => out.push_str("\\\""),
+      '\\' => out.push_str("\\\\"),
+      '\n' => out.push_str("\\n"),
+      '\r' => out.push_str("\\r"),
+      '\t' => out.push_str("\\t"),
+      c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+      c => out.push(c),
+    }
+  }
+  out.push('"');
+  out
+}
+
+/// Write a list of already-written JSON values as an array.
+fn array(items: impl IntoIterator<Item = String>) -> String {
+  format!("[{}]", items.into_iter().collect::<Vec<_>>().join(","))
+}
+
+/// Write pairs of names and already-written JSON values as an object.
+fn object(fields: &[(&str, String)]) -> String {
+  let fields: Vec<_> = fields.iter().map(|(name, value)| format!("{}:{}", string(name), value)).collect();
+  format!("{{{}}}", fields.join(","))
+}
+
+/// Write a number, or null if it's missing or JSON can't hold it.
+fn number(n: Option<f64>) -> String {
+  match n {
+    Some(n) if n.is_finite() => n.to_string(),
+    _ => "null".to_string(),
+  }
+}
+
+/// Write a parameter's value as the JSON value of its type.
+fn value(value: &Value) -> String {
+  match value {
+    Value::Int(i) => i.to_string(),
+    Value::Float(n) | Value::Percent(n) => number(Some(*n)),
+    Value::Flag(on) => on.to_string(),
+    Value::Size(width, height) => array(vec![width.to_string(), height.to_string()]),
+    Value::Point(x, y) => array(vec![number(Some(*x)), number(Some(*y))]),
+    Value::Points(points) => array(points.iter().map(|(x, y)| array(vec![number(Some(*x)), number(Some(*y))]))),
+    Value::Enum(_) | Value::Path(_) => string(&value.to_string()),
+  }
+}
+
+/// The name of a kind of parameter, as it's written in descriptions.
+pub fn kind_name(kind: &Kind) -> &'static str {
+  match kind {
+    Kind::Int { .. } => "int",
+    Kind::Float { .. } => "float",
+    Kind::Percent { .. } => "percent",
+    Kind::Enum(_) => "enum",
+    Kind::Path => "path",
+    Kind::Flag => "flag",
+    Kind::Size => "size",
+    Kind::Point => "point",
+    Kind::Points => "points",
+  }
+}
+
+/// Describe one parameter.
+fn param(param: &Param) -> String {
+  // a default that doesn't parse is a bug in the generator, but it's still worth showing as it's written
+  let default = match param.default {
+    Some(default) => param.parse(default).map_or_else(|_| string(default), |parsed| value(&parsed)),
+    // flags are off unless they're given
+    None if param.kind == Kind::Flag => "false".to_string(),
+    None => "null".to_string(),
+  };
+  let mut fields = vec![
+    ("name", string(param.name)),
+    ("short", param.short.map_or("null".to_string(), |short| string(&short.to_string()))),
+    ("help", string(param.help)),
+    ("kind", string(kind_name(&param.kind))),
+    ("default", default),
+    ("required", param.required.to_string()),
+  ];
+  match &param.kind {
+    Kind::Int { min, max } => {
+      fields.push(("min", min.map_or("null".to_string(), |min| min.to_string())));
+      fields.push(("max", max.map_or("null".to_string(), |max| max.to_string())));
+    }
+    Kind::Float { min, max } | Kind::Percent { min, max } => {
+      fields.push(("min", number(*min)));
+      fields.push(("max", number(*max)));
+    }
+    Kind::Enum(choices) => fields.push(("choices", array(choices.iter().map(|choice| string(choice))))),
+    _ => {}
+  }
+  object(&fields)
+}
+
+/// Describe a generator and every parameter it takes.
+pub fn describe(gen: &dyn Gen) -> String {
+  object(&[
+    ("category", string(gen.category().name())),
+    ("command", string(gen.command())),
+    ("name", string(&format!("{}/{}", gen.category().name(), gen.command()))),
+    ("about", string(gen.about())),
+    ("aliases", array(gen.aliases().iter().map(|alias| string(alias)))),
+    ("tags", array(gen.tags().iter().map(|tag| string(tag)))),
+    ("hidden", gen.hidden().to_string()),
+    ("experimental", gen.experimental().to_string()),
+    ("params", array(gen.params().iter().map(param))),
+  ])
+}
+
+/// List the categories with any of `gens` in them, each with its `name`, `description`, and `generators`, which are
+/// described in full.
+pub fn list(registry: &Registry, gens: &[&'static dyn Gen]) -> String {
+  let categories = registry.categories().into_iter()
+    .filter(|category| gens.iter().any(|gen| gen.category() == *category))
+    .map(|category: Category| object(&[
+      ("name", string(category.name())),
+      ("description", string(category.description())),
+      ("generators", array(gens.iter().filter(|gen| gen.category() == category).map(|gen| describe(*gen)))),
+    ]));
+  array(categories)
+}
+
+/// List the categories with any of `gens` in them as text, a line per category and an indented line per generator
+/// under it, with the generators' names lined up.
+pub fn list_text(registry: &Registry, gens: &[&'static dyn Gen]) -> Vec<String> {
+  let names = |gen: &&dyn Gen| {
+    std::iter::once(gen.command()).chain(gen.aliases().iter().cloned()).collect::<Vec<_>>().join(", ")
+  };
+  let width = gens.iter().map(|gen| names(gen).len()).max().unwrap_or(0);
+  let mut lines = vec![];
+  for category in registry.categories() {
+    let in_category: Vec<_> = gens.iter().filter(|gen| gen.category() == category).collect();
+    if in_category.is_empty() {
+      continue;
+    }
+    lines.push(format!("{}: {}", category.name(), category.description()));
+    for gen in in_category {
+      let experimental = if gen.experimental() { " (experimental)" } else { "" };
+      lines.push(format!("  {:width$}  {}{}", names(gen), gen.about(), experimental, width = width));
+    }
+  }
+  lines
+}
+
+/// What values a kind of parameter takes, in words.
+fn kind_text(kind: &Kind) -> String {
+  fn range<T: fmt::Display>(what: &str, min: &Option<T>, max: &Option<T>) -> String {
+    match (min, max) {
+      (Some(min), Some(max)) => format!("{} from {} to {}", what, min, max),
+      (Some(min), None) => format!("{} from {}", what, min),
+      (None, Some(max)) => format!("{} up to {}", what, max),
+      (None, None) => what.to_string(),
+    }
+  }
+  match kind {
+    Kind::Int { min, max } => range("an integer", min, max),
+    Kind::Float { min, max } => range("a number", min, max),
+    Kind::Percent { min, max } => {
+      let percent = |p: &Option<f64>| p.map(|p| format!("{}%", p));
+      range("a percentage", &percent(min), &percent(max))
+    }
+    Kind::Enum(choices) => format!("one of {}", choices.join(", ")),
+    Kind::Path => "a path, or - for stdin".to_string(),
+    Kind::Flag => "a flag".to_string(),
+    Kind::Size => "a size, like 1920x1080".to_string(),
+    Kind::Point => "a point, like 25%,50%".to_string(),
+    Kind::Points => "a list of points, like 10%,50%;90%,50%".to_string(),
+  }
+}
+
+/// Describe a generator and every parameter it takes as text.
+pub fn describe_text(gen: &dyn Gen) -> Vec<String> {
+  let mut lines = vec![format!("{}/{}: {}", gen.category().name(), gen.command(), gen.about())];
+  if !gen.aliases().is_empty() {
+    lines.push(format!("Also called: {}", gen.aliases().join(", ")));
+  }
+  if !gen.tags().is_empty() {
+    lines.push(format!("Tags: {}", gen.tags().join(", ")));
+  }
+  if gen.experimental() {
+    lines.push(EXPERIMENTAL.to_string());
+  }
+  for param in gen.params() {
+    let short = param.short.map_or(String::new(), |short| format!(", -{}", short));
+    let default = match (param.default, param.required) {
+      (Some(default), _) => format!("; {} by default", default),
+      (None, true) => "; required".to_string(),
+      (None, false) => String::new(),
+    };
+    lines.push(String::new());
+    lines.push(format!("--{}{}: {}{}", param.name, short, kind_text(&param.kind), default));
+    lines.push(format!("    {}", param.help));
+  }
+  lines
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn strings_are_escaped() {
+    assert_eq!(string("plain"), r#""plain""#);
+    assert_eq!(string("say \"hi\"\\\n"), r#""say \"hi\"\\\n""#);
+    assert_eq!(string("\u{1}"), r#""\u0001""#);
+  }
+
+  #[test]
+  fn params_carry_their_whole_schema() {
+    let octaves = Param::new("octaves", Kind::Int { min: Some(1), max: None }).short('O').help("Layers").default("1");
+    assert_eq!(
+      param(&octaves),
+      r#"{"name":"octaves","short":"O","help":"Layers","kind":"int","default":1,"required":false,"min":1,"max":null}"#,
+    );
+    let size = Param::new("size", Kind::Size).default("4x3");
+    assert!(param(&size).contains(r#""kind":"size","default":[4,3]"#));
+    let algorithm = Param::new("algorithm", Kind::Enum(&["perlin", "worley"])).required();
+    assert!(param(&algorithm).ends_with(r#""default":null,"required":true,"choices":["perlin","worley"]}"#));
+    let scale = Param::new("scale", Kind::Float { min: Some(0.5), max: None }).default("2");
+    assert!(param(&scale).ends_with(r#""default":2,"required":false,"min":0.5,"max":null}"#));
+    let start = Param::new("start", Kind::Percent { min: Some(0.0), max: Some(100.0) }).default("10%");
+    assert!(param(&start).ends_with(r#""kind":"percent","default":10,"required":false,"min":0,"max":100}"#));
+  }
+
+  #[test]
+  fn lists_only_have_the_categories_asked_about() {
+    let registry = Registry::builtin();
+    let text: Vec<_> = registry.tagged("text").collect();
+    let listed = list(&registry, &text);
+    assert!(listed.starts_with(r#"[{"name":"test","description":"#));
+    assert!(listed.contains(r#""name":"test/ascii""#));
+    assert!(!listed.contains(r#""name":"basic""#));
+  }
+}
@@ -0,0 +1,102 @@
+//! All the different ways to write down a seed, and how to turn them into the raw bytes generators are seeded with.
+//! Every format round-trips, so a seed printed with `to_hex` can be pasted back in with `--seed-hex`.
+
+use rand::{rngs::OsRng, RngCore as _};
+
+/// How many bytes `random` generates. Matches ForeveRNG's internal state, so more wouldn't help.
+const RANDOM_SZ: usize = 16;
+
+/// Decode a seed written in hexadecimal, e.g. `"deadbeef"`. Case doesn't matter, but there must be an even number
+/// of digits.
+pub fn from_hex(s: &str) -> Result<Vec<u8>, String> {
+  if !s.len().is_multiple_of(2) {
+    return Err(format!("{} has an odd number of hex digits", s));
+  }
+  (0..s.len())
+    .step_by(2)
+    .map(|i| {
+      s.get(i..i + 2)
+        .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        .ok_or_else(|| format!("{} is not valid hex", s))
+    })
+    .collect()
+}
+
+/// Encode a seed as lowercase hexadecimal.
+pub fn to_hex(seed: &[u8]) -> String {
+  seed.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decode a seed written in standard, padded base64.
+pub fn from_b64(s: &str) -> Result<Vec<u8>, String> {
+  base64::decode(s).map_err(|e| format!("{} is not valid base64: {}", s, e))
+}
+
+/// Encode a seed as standard, padded base64.
+pub fn to_b64(seed: &[u8]) -> String {
+  base64::encode(seed)
+}
+
+/// Decode a seed written as a nonnegative integer. The seed is the number's 8 big-endian bytes, so every integer
+/// that fits in a u64 produces a different seed.
+pub fn from_int(s: &str) -> Result<Vec<u8>, String> {
+  match s.parse::<u64>() {
+    Ok(n) => Ok(n.to_be_bytes().to_vec()),
+    Err(_) => Err(format!("{} is not a nonnegative integer in range", s)),
+  }
+}
+
+/// Pull a fresh seed from the operating system's random number generator.
+pub fn random() -> Vec<u8> {
+  let mut seed = vec![0; RANDOM_SZ];
+  OsRng.fill_bytes(&mut seed);
+  seed
+}
+
+/// Strip a single trailing newline (`\n` or `\r\n`), the way a line read from a terminal or `echo` ends.
+pub fn trim_newline(mut seed: Vec<u8>) -> Vec<u8> {
+  if seed.ends_with(b"\n") {
+    seed.pop();
+    if seed.ends_with(b"\r") {
+      seed.pop();
+    }
+  }
+  seed
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn hex_round_trips() {
+    let seed = vec![0x00, 0x7f, 0x80, 0xff, 0x12];
+    assert_eq!(to_hex(&seed), "007f80ff12");
+    assert_eq!(from_hex("007F80ff12").unwrap(), seed);
+    assert!(from_hex("abc").is_err());
+    assert!(from_hex("zz").is_err());
+    assert!(from_hex("é0").is_err());
+  }
+
+  #[test]
+  fn b64_round_trips() {
+    let seed = random();
+    assert_eq!(from_b64(&to_b64(&seed)).unwrap(), seed);
+    assert!(from_b64("not base64!").is_err());
+  }
+
+  #[test]
+  fn ints_are_distinct() {
+    assert_eq!(from_int("258").unwrap(), vec![0, 0, 0, 0, 0, 0, 1, 2]);
+    assert_ne!(from_int("0").unwrap(), from_int("1").unwrap());
+    assert!(from_int("-1").is_err());
+  }
+
+  #[test]
+  fn trims_one_newline() {
+    assert_eq!(trim_newline(b"seed\n".to_vec()), b"seed");
+    assert_eq!(trim_newline(b"seed\r\n".to_vec()), b"seed");
+    assert_eq!(trim_newline(b"seed\n\n".to_vec()), b"seed\n");
+    assert_eq!(trim_newline(b"seed".to_vec()), b"seed");
+  }
+}
@@ -0,0 +1,104 @@
+//! Contact sheets: a grid of thumbnails of a generator's variants, each captioned with what makes it different, so
+//! the best ones can be picked out at a glance before rendering them at full size.
+
+use {
+  crate::{
+    gens::{Gen, GenFail, Result},
+    output::Output,
+    params::Params,
+    utils::{
+      font,
+      render::{Renderer, Tile, View},
+    },
+  },
+  image::{imageops, Rgb, RgbImage},
+};
+
+/// The space around and between thumbnails, in pixels.
+const GAP: u32 = 8;
+/// The space between lines of captions, in pixels.
+const LINE_GAP: u32 = 2;
+const BACKGROUND: Rgb<u8> = Rgb([32, 32, 32]);
+const TEXT: Rgb<u8> = Rgb([224, 224, 224]);
+
+/// Render a generator's output no bigger than `size` pixels on either side, keeping its shape. Generators which can
+/// render at any scale are rendered straight at that size, sampling the same points as the full-size render would;
+/// the rest are rendered in full and scaled down.
+pub fn thumbnail(gen: &dyn Gen, params: &Params, seed: &[u8], size: u32) -> Result<RgbImage> {
+  // how much to shrink something to fit, and how big each side ends up
+  let shrink = |width: u32, height: u32| (width.max(height) as f32 / size as f32).max(1.0);
+  let fit = |side: u32, scale: f32| ((side as f32 / scale) as u32).clamp(1, size);
+  if let Some(raster) = gen.raster(params, seed)? {
+    let (width, height) = raster.dimensions();
+    let scale = shrink(width, height);
+    let view = View { area: Tile::whole(fit(width, scale), fit(height, scale)), scale };
+    return Ok(raster.render(&Renderer::new().quiet(), view).to_dynamic().to_rgb8());
+  }
+  let image = match gen.run(params, seed)? {
+    Output::Image(image) => image.to_dynamic().to_rgb8(),
+    Output::Raw(_) => return Err(GenFail::BadArg(format!("{} doesn't make images", gen.command()))),
+  };
+  let scale = shrink(image.width(), image.height());
+  Ok(imageops::thumbnail(&image, fit(image.width(), scale), fit(image.height(), scale)))
+}
+
+/// Lay thumbnails out in a grid, `columns` across, with each one's lines of caption underneath it. Thumbnails
+/// smaller than the biggest are centered in their spot, and captions too wide for it are cut short.
+pub fn compose(cells: &[(RgbImage, Vec<String>)], columns: u32) -> RgbImage {
+  let columns = columns.clamp(1, cells.len().max(1) as u32);
+  let rows = (cells.len() as u32).div_ceil(columns);
+  let thumb_width = cells.iter().map(|(thumb, _)| thumb.width()).max().unwrap_or(0);
+  let thumb_height = cells.iter().map(|(thumb, _)| thumb.height()).max().unwrap_or(0);
+  // text twice the size is easier to read, once there's room for it
+  let scale = if thumb_width >= 256 { 2 } else { 1 };
+  let lines = cells.iter().map(|(_, caption)| caption.len() as u32).max().unwrap_or(0);
+  let caption_height = lines * (font::HEIGHT * scale + LINE_GAP);
+  let cell_width = thumb_width + GAP;
+  let cell_height = thumb_height + caption_height + GAP;
+
+  let mut sheet = RgbImage::from_pixel(columns * cell_width + GAP, rows * cell_height + GAP, BACKGROUND);
+  for (i, (thumb, caption)) in cells.iter().enumerate() {
+    let (x, y) = (GAP + i as u32 % columns * cell_width, GAP + i as u32 / columns * cell_height);
+    let (dx, dy) = ((thumb_width - thumb.width()) / 2, (thumb_height - thumb.height()) / 2);
+    imageops::replace(&mut sheet, thumb, x + dx, y + dy);
+    for (line, text) in caption.iter().enumerate() {
+      let text = font::fit(text, thumb_width, scale);
+      let top = y + thumb_height + LINE_GAP + line as u32 * (font::HEIGHT * scale + LINE_GAP);
+      font::draw(&mut sheet, x, top, &text, TEXT, scale);
+    }
+  }
+  sheet
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sheets_fit_every_cell() {
+    let cells: Vec<_> = (0..5u8)
+      .map(|i| (RgbImage::from_pixel(20, 10 + i as u32, Rgb([i * 50, 0, 0])), vec![format!("seed {}", i)]))
+      .collect();
+    let sheet = compose(&cells, 2);
+    let (cell_width, cell_height) = (20 + GAP, 14 + font::HEIGHT + LINE_GAP + GAP);
+    assert_eq!(sheet.dimensions(), (2 * cell_width + GAP, 3 * cell_height + GAP));
+    // the last thumbnail is the tallest, in the first column of the third row
+    assert_eq!(sheet.get_pixel(GAP, GAP + 2 * cell_height), &Rgb([200, 0, 0]));
+    // the first is centered vertically in its spot
+    assert_eq!(sheet.get_pixel(GAP, GAP + 1), &BACKGROUND);
+    assert_eq!(sheet.get_pixel(GAP, GAP + 2), &Rgb([0, 0, 0]));
+    // and every caption is drawn
+    let caption = |i: u32| {
+      let (x, y) = (GAP + i % 2 * cell_width, GAP + i / 2 * cell_height + 14 + LINE_GAP);
+      (x..x + 20).any(|x| (y..y + font::HEIGHT).any(|y| sheet.get_pixel(x, y) == &TEXT))
+    };
+    assert!((0..5).all(caption));
+  }
+
+  #[test]
+  fn columns_are_kept_sensible() {
+    let cells = vec![(RgbImage::new(10, 10), vec![]); 3];
+    assert_eq!(compose(&cells, 0).width(), compose(&cells, 1).width());
+    assert_eq!(compose(&cells, 10).width(), compose(&cells, 3).width());
+  }
+}
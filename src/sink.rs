@@ -0,0 +1,76 @@
+//! Somewhere to write output, which only shows up once it's been written in full.
+
+use {
+  crate::{
+    gens::STDIO,
+    run::{Exit, Failure, OrExit as _},
+  },
+  std::{
+    ffi::OsString,
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+  },
+};
+
+/// Where output goes: stdout, or a file. Files are written under a temporary name next to where they're going, and
+/// only moved into place by `finish`, so a failure never leaves a half-written file behind.
+pub struct Sink {
+  out: BufWriter<Box<dyn Write>>,
+  /// The temporary path being written, and where it goes once it's done
+  paths: Option<(PathBuf, PathBuf)>,
+}
+
+impl Sink {
+  /// Start writing to a path, where `-` means stdout.
+  pub fn create(path: &Path) -> Result<Sink, Failure> {
+    if path == Path::new(STDIO) {
+      return Ok(Sink { out: BufWriter::new(Box::new(io::stdout().lock())), paths: None });
+    }
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".part");
+    let temp = path.with_file_name(name);
+    let file = fs::File::create(&temp).or_exit(Exit::CantCreate, || format!("couldn't create {}", path.display()))?;
+    Ok(Sink { out: BufWriter::new(Box::new(file)), paths: Some((temp, path.to_path_buf())) })
+  }
+
+  /// What's being written to, for error messages.
+  pub fn name(&self) -> String {
+    match &self.paths {
+      Some((_, path)) => path.display().to_string(),
+      None => "stdout".to_string(),
+    }
+  }
+
+  /// Finish writing, moving the file into place.
+  pub fn finish(mut self) -> Result<(), Failure> {
+    let name = self.name();
+    self.out.flush().or_exit(Exit::Io, || format!("couldn't write {}", name))?;
+    if let Some((temp, path)) = &self.paths {
+      fs::rename(temp, path).or_exit(Exit::CantCreate, || format!("couldn't create {}", name))?;
+    }
+    self.paths = None;
+    Ok(())
+  }
+}
+
+impl Write for Sink {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.out.write(buf)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.out.flush()
+  }
+}
+
+impl Drop for Sink {
+  fn drop(&mut self) {
+    if let Some((temp, _)) = self.paths.take() {
+      // close the file first, or it can't be removed everywhere
+      self.out = BufWriter::new(Box::new(io::sink()));
+      let _ = fs::remove_file(temp);
+    }
+  }
+}
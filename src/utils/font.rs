@@ -0,0 +1,200 @@
+//! A tiny bitmap font, for putting labels on images without depending on a font file or a text rendering library.
+//!
+//! Every printable ASCII character is a 5x7 grid of pixels, drawn at any whole-number scale. Anything else is drawn as
+//! `?`.
+
+use image::{Rgb, RgbImage};
+
+/// How many pixels wide each character is, at a scale of 1.
+pub const WIDTH: u32 = 5;
+/// How many pixels tall each character is, at a scale of 1.
+pub const HEIGHT: u32 = 7;
+/// How many pixels are left between characters, at a scale of 1.
+pub const SPACING: u32 = 1;
+
+/// The characters from ' ' to '~', one row per byte from the top down, with the leftmost pixel in bit 4.
+const GLYPHS: [[u8; HEIGHT as usize]; 95] = [
+  [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+  [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04], // '!'
+  [0x0a, 0x0a, 0x0a, 0x00, 0x00, 0x00, 0x00], // '"'
+  [0x0a, 0x0a, 0x1f, 0x0a, 0x1f, 0x0a, 0x0a], // '#'
+  [0x04, 0x0f, 0x14, 0x0e, 0x05, 0x1e, 0x04], // '$'
+  [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03], // '%'
+  [0x0c, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0d], // '&'
+  [0x04, 0x04, 0x04, 0x00, 0x00, 0x00, 0x00], // '\''
+  [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02], // '('
+  [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08], // ')'
+  [0x00, 0x04, 0x15, 0x0e, 0x15, 0x04, 0x00], // '*'
+  [0x00, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x00], // '+'
+  [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08], // ','
+  [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00], // '-'
+  [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c], // '.'
+  [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00], // '/'
+  [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e], // '0'
+  [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e], // '1'
+  [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f], // '2'
+  [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e], // '3'
+  [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02], // '4'
+  [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e], // '5'
+  [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e], // '6'
+  [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08], // '7'
+  [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e], // '8'
+  [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c], // '9'
+  [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00], // ':'
+  [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x04, 0x08], // ';'
+  [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02], // '<'
+  [0x00, 0x00, 0x1f, 0x00, 0x1f, 0x00, 0x00], // '='
+  [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08], // '>'
+  [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], // '?'
+  [0x0e, 0x11, 0x01, 0x0d, 0x15, 0x15, 0x0e], // '@'
+  [0x0e, 0x11, 0x11, 0x11, 0x1f, 0x11, 0x11], // 'A'
+  [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e], // 'B'
+  [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e], // 'C'
+  [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c], // 'D'
+  [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f], // 'E'
+  [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10], // 'F'
+  [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f], // 'G'
+  [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11], // 'H'
+  [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e], // 'I'
+  [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c], // 'J'
+  [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11], // 'K'
+  [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f], // 'L'
+  [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11], // 'M'
+  [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], // 'N'
+  [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e], // 'O'
+  [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10], // 'P'
+  [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d], // 'Q'
+  [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11], // 'R'
+  [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e], // 'S'
+  [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // 'T'
+  [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e], // 'U'
+  [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04], // 'V'
+  [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a], // 'W'
+  [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11], // 'X'
+  [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04], // 'Y'
+  [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f], // 'Z'
+  [0x0e, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0e], // '['
+  [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00], // '\\'
+  [0x0e, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0e], // ']'
+  [0x04, 0x0a, 0x11, 0x00, 0x00, 0x00, 0x00], // '^'
+  [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f], // '_'
+  [0x08, 0x04, 0x02, 0x00, 0x00, 0x00, 0x00], // '`'
+  [0x00, 0x00, 0x0e, 0x01, 0x0f, 0x11, 0x0f], // 'a'
+  [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1e], // 'b'
+  [0x00, 0x00, 0x0e, 0x10, 0x10, 0x11, 0x0e], // 'c'
+  [0x01, 0x01, 0x0d, 0x13, 0x11, 0x11, 0x0f], // 'd'
+  [0x00, 0x00, 0x0e, 0x11, 0x1f, 0x10, 0x0e], // 'e'
+  [0x06, 0x09, 0x08, 0x1c, 0x08, 0x08, 0x08], // 'f'
+  [0x00, 0x0f, 0x11, 0x11, 0x0f, 0x01, 0x0e], // 'g'
+  [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11], // 'h'
+  [0x04, 0x00, 0x0c, 0x04, 0x04, 0x04, 0x0e], // 'i'
+  [0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0c], // 'j'
+  [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12], // 'k'
+  [0x0c, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e], // 'l'
+  [0x00, 0x00, 0x1a, 0x15, 0x15, 0x11, 0x11], // 'm'
+  [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11], // 'n'
+  [0x00, 0x00, 0x0e, 0x11, 0x11, 0x11, 0x0e], // 'o'
+  [0x00, 0x00, 0x1e, 0x11, 0x1e, 0x10, 0x10], // 'p'
+  [0x00, 0x00, 0x0d, 0x13, 0x0f, 0x01, 0x01], // 'q'
+  [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10], // 'r'
+  [0x00, 0x00, 0x0e, 0x10, 0x0e, 0x01, 0x1e], // 's'
+  [0x08, 0x08, 0x1c, 0x08, 0x08, 0x09, 0x06], // 't'
+  [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0d], // 'u'
+  [0x00, 0x00, 0x11, 0x11, 0x11, 0x0a, 0x04], // 'v'
+  [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0a], // 'w'
+  [0x00, 0x00, 0x11, 0x0a, 0x04, 0x0a, 0x11], // 'x'
+  [0x00, 0x00, 0x11, 0x11, 0x0f, 0x01, 0x0e], // 'y'
+  [0x00, 0x00, 0x1f, 0x02, 0x04, 0x08, 0x1f], // 'z'
+  [0x02, 0x04, 0x04, 0x08, 0x04, 0x04, 0x02], // '{'
+  [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // '|'
+  [0x08, 0x04, 0x04, 0x02, 0x04, 0x04, 0x08], // '}'
+  [0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00], // '~'
+];
+
+/// The pixels of a character, falling back to `?` for anything that isn't printable ASCII.
+fn glyph(ch: char) -> &'static [u8; HEIGHT as usize] {
+  let index = match ch {
+    ' '..='~' => ch as usize - ' ' as usize,
+    _ => '?' as usize - ' ' as usize,
+  };
+  &GLYPHS[index]
+}
+
+/// How many pixels wide a line of text is at some scale.
+pub fn width(text: &str, scale: u32) -> u32 {
+  let chars = text.chars().count() as u32;
+  (chars * (WIDTH + SPACING)).saturating_sub(SPACING) * scale
+}
+
+/// Shorten a line of text to fit in some number of pixels, ending it with `..` if anything had to be cut.
+pub fn fit(text: &str, max_width: u32, scale: u32) -> String {
+  if width(text, scale) <= max_width {
+    return text.to_string();
+  }
+  let chars = ((max_width / scale + SPACING) / (WIDTH + SPACING)) as usize;
+  let kept: String = text.chars().take(chars.saturating_sub(2)).collect();
+  format!("{}{}", kept, &".."[..chars.min(2)])
+}
+
+/// Draw a line of text with its top-left corner at `(x, y)`. Anything falling off the edge of the image is skipped.
+pub fn draw(img: &mut RgbImage, x: u32, y: u32, text: &str, color: Rgb<u8>, scale: u32) {
+  for (i, ch) in text.chars().enumerate() {
+    let left = x + i as u32 * (WIDTH + SPACING) * scale;
+    for (row, bits) in glyph(ch).iter().enumerate() {
+      for col in 0..WIDTH {
+        if bits & (1 << (WIDTH - 1 - col)) == 0 {
+          continue;
+        }
+        for dy in 0..scale {
+          for dx in 0..scale {
+            let (px, py) = (left + col * scale + dx, y + row as u32 * scale + dy);
+            if px < img.width() && py < img.height() {
+              img.put_pixel(px, py, color);
+            }
+          }
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn every_glyph_is_different() {
+    for (i, a) in GLYPHS.iter().enumerate() {
+      assert!(a.iter().all(|row| row >> WIDTH == 0), "{:?} is too wide", (b' ' + i as u8) as char);
+      for (j, b) in GLYPHS.iter().enumerate().skip(i + 1) {
+        assert_ne!(a, b, "{:?} and {:?} look the same", (b' ' + i as u8) as char, (b' ' + j as u8) as char);
+      }
+    }
+    assert_eq!(glyph('é'), glyph('?'));
+  }
+
+  #[test]
+  fn text_is_drawn_where_it_says() {
+    let mut img = RgbImage::new(20, 10);
+    draw(&mut img, 1, 2, "|", Rgb([255, 255, 255]), 1);
+    let lit: Vec<_> = img.enumerate_pixels().filter(|(_, _, p)| p[0] == 255).map(|(x, y, _)| (x, y)).collect();
+    assert_eq!(lit, (2..9).map(|y| (3, y)).collect::<Vec<_>>());
+
+    let mut big = RgbImage::new(20, 20);
+    draw(&mut big, 0, 0, "..", Rgb([255, 255, 255]), 2);
+    assert_eq!(big.pixels().filter(|p| p[0] == 255).count(), 2 * 4 * 4);
+    // off the edge is fine, it just isn't drawn
+    draw(&mut big, 15, 15, "WWW", Rgb([255, 255, 255]), 2);
+  }
+
+  #[test]
+  fn long_text_is_cut_to_fit() {
+    assert_eq!(width("abc", 1), 17);
+    assert_eq!(width("abc", 2), 34);
+    assert_eq!(width("", 1), 0);
+    assert_eq!(fit("octaves=3", 100, 1), "octaves=3");
+    assert_eq!(fit("octaves=3", 35, 1), "octa..");
+    assert!(width(&fit("a long line of text", 50, 2), 2) <= 50);
+    assert_eq!(fit("abc", 3, 1), "");
+  }
+}
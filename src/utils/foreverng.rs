@@ -12,7 +12,7 @@ use {
     aes::KeySize, aesni, md5,
     digest::Digest as _, symmetriccipher::BlockEncryptor as _
   },
-  rand::{Error, RngCore},
+  rand::{Error, RngCore, SeedableRng},
 };
 
 const KEY_SZ: usize = 128 / 8; // MD5 outputs 128-bit keys so
@@ -21,7 +21,7 @@ const BLOCK_SZ: usize = 16;
 fn chunks(seed: &[u8]) -> Vec<[u8; BLOCK_SZ]> {
   let mut res = Vec::with_capacity(seed.len() / BLOCK_SZ + 1);
   for start in (0..seed.len()).step_by(BLOCK_SZ) {
-    let end = BLOCK_SZ.min(seed.len() - start);
+    let end = start + BLOCK_SZ.min(seed.len() - start);
     let in_chunk = &seed[start..end];
     let mut out_chunk = [0; BLOCK_SZ];
     out_chunk[..in_chunk.len()].copy_from_slice(in_chunk);
@@ -56,6 +56,8 @@ fn seed_round(enc: &aesni::AesNiEncryptor, last: &[u8], mut seed: [u8; BLOCK_SZ]
 /// encrypting infinite zeroes.
 /// 
 /// Reseeding is similar; it uses the internal state of the parent for the IV instead of an all-zero one.
+/// Deriving is just reseeding repeatedly, once per component of a `/`-separated path.
+#[derive(Clone)]
 pub struct ForeveRNG {
   next: [u8; BLOCK_SZ],
   left: usize,
@@ -96,6 +98,15 @@ impl ForeveRNG {
     Self::new(self.next, new_seed)
   }
 
+  /// Base a new ForeveRNG on an existing one by following a path of seeds, e.g. `"layer/red/octave-2"`.
+  /// Each `/`-separated component is fed to `reseed` in turn, so `derive("a/b")` produces the same sequence as
+  /// `reseed(b"a").reseed(b"b")`. Empty components are ignored, so an empty path is just a copy of the parent.
+  pub fn derive(&self, path: &str) -> ForeveRNG {
+    path.split('/')
+      .filter(|part| !part.is_empty())
+      .fold(self.clone(), |rng, part| rng.reseed(part.as_bytes()))
+  }
+
   fn refill(&mut self) {
     let mut new_data = [0; BLOCK_SZ];
     self.enc.encrypt_block(&self.next, &mut new_data);
@@ -106,7 +117,7 @@ impl ForeveRNG {
 
 impl RngCore for ForeveRNG {
   fn fill_bytes(&mut self, dest: &mut [u8]) {
-    if dest.len() == 0 {
+    if dest.is_empty() {
       // very easy: nothing to copy
       return;
     }
@@ -125,7 +136,7 @@ impl RngCore for ForeveRNG {
       let start = self.next.len() - self.left;
       dest[0..self.left].copy_from_slice(&self.next[start..]);
       pos += self.left;
-      self.left -= 0;
+      self.left = 0;
     }
     // then, as long as there's more than one chunk size left...
     while dest.len() - pos > BLOCK_SZ {
@@ -139,6 +150,7 @@ impl RngCore for ForeveRNG {
     self.refill();
     let final_copy = dest.len() - pos;
     dest[pos..].copy_from_slice(&self.next[..final_copy]);
+    self.left = BLOCK_SZ - final_copy;
   }
 
   fn next_u32(&mut self) -> u32 {
@@ -158,3 +170,109 @@ impl RngCore for ForeveRNG {
     Ok(())
   }
 }
+
+impl SeedableRng for ForeveRNG {
+  // The seed can be as long as you want, but rand needs a fixed size. One block seems reasonable.
+  type Seed = [u8; BLOCK_SZ];
+
+  fn from_seed(seed: Self::Seed) -> ForeveRNG {
+    Self::with_seed(&seed)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    crate::utils::stats,
+  };
+
+  const SAMPLE_SZ: usize = 1 << 20;
+
+  fn sample(mut rng: ForeveRNG) -> Vec<u8> {
+    let mut data = vec![0; SAMPLE_SZ];
+    rng.fill_bytes(&mut data);
+    data
+  }
+
+  #[test]
+  fn output_looks_random() {
+    stats::assert_random("short seed", &sample(ForeveRNG::with_seed(b"hart")));
+    stats::assert_random("empty seed", &sample(ForeveRNG::with_seed(b"")));
+    stats::assert_random("long seed", &sample(ForeveRNG::with_seed(&[0xA5; 100])));
+  }
+
+  #[test]
+  fn small_reads_look_random() {
+    // Exercise the partial-block paths in fill_bytes, which one big read skips almost entirely.
+    let mut rng = ForeveRNG::with_seed(b"small reads");
+    let mut data = vec![0; SAMPLE_SZ];
+    let mut pos = 0;
+    for size in [1, 3, 5, 7, 11, 13, 16, 17, 31].iter().cycle() {
+      let end = (pos + size).min(data.len());
+      rng.fill_bytes(&mut data[pos..end]);
+      pos = end;
+      if pos == data.len() {
+        break;
+      }
+    }
+    stats::assert_random("small reads", &data);
+  }
+
+  #[test]
+  fn read_size_does_not_change_output() {
+    let whole = sample(ForeveRNG::with_seed(b"chunking"));
+    let mut rng = ForeveRNG::with_seed(b"chunking");
+    let mut pieces = vec![0; SAMPLE_SZ];
+    for chunk in pieces.chunks_mut(20) {
+      rng.fill_bytes(chunk);
+    }
+    assert!(whole == pieces, "reading in chunks produced a different stream than reading all at once");
+  }
+
+  #[test]
+  fn reseeds_look_random() {
+    let parent = ForeveRNG::with_seed(b"parent");
+    stats::assert_random("reseed", &sample(parent.reseed(b"child")));
+    stats::assert_random("derive", &sample(parent.derive("layer/red/octave-2")));
+  }
+
+  #[test]
+  fn similar_seeds_are_independent() {
+    // If two streams are independent, XORing them together should still look random.
+    // If they share structure, it'll show up as a bias in the XOR.
+    let parent = ForeveRNG::with_seed(b"parent");
+    let pairs = [
+      ("one bit apart", ForeveRNG::with_seed(b"seed0"), ForeveRNG::with_seed(b"seed1")),
+      ("trailing zero", ForeveRNG::with_seed(b"seed"), ForeveRNG::with_seed(b"seed\0")),
+      ("block boundary", ForeveRNG::with_seed(&[1; 16]), ForeveRNG::with_seed(&[1; 17])),
+      ("sibling reseeds", parent.reseed(b"a"), parent.reseed(b"b")),
+      ("parent and child", parent.clone(), parent.reseed(b"")),
+      ("sibling paths", parent.derive("octave-1"), parent.derive("octave-2")),
+    ];
+    for (name, a, b) in pairs.iter() {
+      let a = sample(a.clone());
+      let b = sample(b.clone());
+      let xor: Vec<u8> = a.iter().zip(b.iter()).map(|(a, b)| a ^ b).collect();
+      stats::assert_random(name, &xor);
+    }
+  }
+
+  #[test]
+  fn derive_matches_reseed() {
+    let parent = ForeveRNG::with_seed(b"parent");
+    let mut derived = parent.derive("layer/red");
+    let mut reseeded = parent.reseed(b"layer").reseed(b"red");
+    assert_eq!(derived.next_u64(), reseeded.next_u64());
+    let mut with_empties = parent.derive("/layer//red/");
+    assert_eq!(with_empties.next_u64(), parent.derive("layer/red").next_u64());
+  }
+
+  #[test]
+  fn same_seed_same_output() {
+    assert_eq!(ForeveRNG::with_seed(b"again").next_u64(), ForeveRNG::with_seed(b"again").next_u64());
+    assert_eq!(ForeveRNG::from_seed([3; BLOCK_SZ]).next_u64(), ForeveRNG::with_seed(&[3; BLOCK_SZ]).next_u64());
+    let parent = ForeveRNG::with_seed(b"parent");
+    assert_eq!(parent.reseed(b"child").next_u64(), parent.reseed(b"child").next_u64());
+  }
+}
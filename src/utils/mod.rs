@@ -1,3 +1,9 @@
+pub mod font;
 pub mod noise;
 mod foreverng;
 pub use foreverng::ForeveRNG;
+pub mod render;
+mod span;
+pub use span::Span;
+#[cfg(test)]
+pub mod stats;
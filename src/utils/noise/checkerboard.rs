@@ -18,6 +18,10 @@ impl Checkerboard {
       rng: ForeveRNG::with_seed(seed),
     }
   }
+
+  pub fn from_rng(rng: ForeveRNG) -> Checkerboard {
+    Checkerboard { rng }
+  }
 }
 
 impl super::Noise2D for Checkerboard {
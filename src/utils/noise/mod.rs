@@ -192,3 +192,75 @@ impl<N: Noise2D> Noise2D for Invert<N> {
     1.0 - self.orig.get(p)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    crate::utils::stats,
+  };
+
+  /// Sample `noise` over a grid of points, deliberately not lined up with the unit grid most noise is built on.
+  fn sample(noise: &impl Noise2D) -> Vec<f32> {
+    let mut samples = Vec::with_capacity(100 * 100);
+    for y in 0..100 {
+      for x in 0..100 {
+        samples.push(noise.get(Pos::of(x as f32 * 1.73 - 50.0, y as f32 * 1.61 - 50.0)));
+      }
+    }
+    samples
+  }
+
+  /// Check that every sample is in range and the mean/variance are where we expect.
+  fn check(name: &str, noise: &impl Noise2D, mean_range: (f64, f64), min_variance: f64) {
+    let samples = sample(noise);
+    for s in samples.iter() {
+      assert!((0.0..=1.0).contains(s), "{}: sample {} is out of range", name, s);
+    }
+    let (mean, variance) = stats::mean_variance(&samples);
+    assert!(
+      mean_range.0 < mean && mean < mean_range.1,
+      "{}: mean {} is outside {:?}", name, mean, mean_range,
+    );
+    assert!(variance > min_variance, "{}: variance {} is too low", name, variance);
+  }
+
+  #[test]
+  fn checkerboard_distribution() {
+    // it's a coin flip per square, so mean and variance should both be basically exactly 0.5 and 0.25
+    check("checkerboard", &Checkerboard::new(b"test"), (0.47, 0.53), 0.24);
+    check("checkerboard octaves", &Checkerboard::new(b"test").octaves().count(3).zoom(3.0).scale(0.8), (0.47, 0.53), 0.05);
+  }
+
+  #[test]
+  fn worley_distribution() {
+    // the distance to the nearest of a bunch of uniformly scattered points is biased towards small values
+    check("worley", &Worley::new(b"test"), (0.3, 0.5), 0.01);
+    check("worley octaves", &Worley::new(b"test").octaves().count(3).zoom(2.0).scale(0.5), (0.3, 0.5), 0.005);
+    check("worley inverted", &Worley::new(b"test").invert(), (0.5, 0.7), 0.01);
+  }
+
+  #[test]
+  fn invert_is_exact() {
+    let plain = sample(&Worley::new(b"test"));
+    let inverted = sample(&Worley::new(b"test").invert());
+    for (p, i) in plain.iter().zip(inverted.iter()) {
+      assert_eq!(1.0 - p, *i);
+    }
+  }
+
+  #[test]
+  fn different_seeds_differ() {
+    let a = sample(&Worley::new(b"one"));
+    let b = sample(&Worley::new(b"two"));
+    let same = a.iter().zip(b.iter()).filter(|(a, b)| a == b).count();
+    assert!(same < a.len() / 100, "{} of {} samples were identical between seeds", same, a.len());
+  }
+
+  #[test]
+  fn noise_is_deterministic() {
+    let p = Pos::of(12.34, -56.78);
+    assert_eq!(Worley::new(b"same").get(p), Worley::new(b"same").get(p));
+    assert_eq!(Checkerboard::new(b"same").get(p), Checkerboard::new(b"same").get(p));
+  }
+}
@@ -20,6 +20,10 @@ impl Worley {
       rng: ForeveRNG::with_seed(seed),
     }
   }
+
+  pub fn from_rng(rng: ForeveRNG) -> Worley {
+    Worley { rng }
+  }
 }
 
 impl super::Noise2D for Worley {
@@ -0,0 +1,464 @@
+//! Rendering images one pixel at a time, in parallel.
+//!
+//! Most generators boil down to "compute each pixel from its coordinates", which is embarrassingly parallel. The
+//! `Renderer` splits the image into tiles, hands them out to rayon's work-stealing pool, and stitches the results
+//! back together, so a generator only has to say what each pixel should be.
+
+use {
+  crate::{output::Image, utils::Span},
+  image::{ImageBuffer, Pixel},
+  rayon::iter::{IntoParallelIterator, ParallelIterator},
+  std::{
+    marker::PhantomData,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+  },
+};
+
+/// How far along rendering is, counted in pixels, for progress bars. Every renderer reports the pixels it finishes to
+/// the one global `Progress`, so frontends can watch it from another thread without generators having to know.
+/// When several images are rendered at once, it can count whole jobs instead; see `start_jobs`.
+#[derive(Debug)]
+pub struct Progress {
+  done: AtomicU64,
+  total: AtomicU64,
+  /// Whether it's counting jobs, and should ignore renderers counting pixels
+  jobs: AtomicBool,
+}
+
+static PROGRESS: Progress = Progress {
+  done: AtomicU64::new(0),
+  total: AtomicU64::new(0),
+  jobs: AtomicBool::new(false),
+};
+
+impl Progress {
+  pub fn global() -> &'static Progress {
+    &PROGRESS
+  }
+
+  /// Start counting towards a new total, forgetting about anything rendered before.
+  pub fn start(&self, pixels: u64) {
+    if !self.jobs.load(Ordering::Relaxed) {
+      self.done.store(0, Ordering::Relaxed);
+      self.total.store(pixels, Ordering::Relaxed);
+    }
+  }
+
+  fn finish(&self, pixels: u64) {
+    if !self.jobs.load(Ordering::Relaxed) {
+      self.done.fetch_add(pixels, Ordering::Relaxed);
+    }
+  }
+
+  /// Count whole jobs rather than pixels until the returned `Jobs` is dropped, for when several images are rendered
+  /// at once and their renderers would otherwise keep restarting the count.
+  pub fn start_jobs(&'static self, jobs: u64) -> Jobs {
+    self.jobs.store(true, Ordering::Relaxed);
+    self.done.store(0, Ordering::Relaxed);
+    self.total.store(jobs, Ordering::Relaxed);
+    Jobs { progress: self }
+  }
+
+  /// How many pixels have been rendered, out of how many.
+  pub fn get(&self) -> (u64, u64) {
+    (self.done.load(Ordering::Relaxed), self.total.load(Ordering::Relaxed))
+  }
+}
+
+/// Counts jobs on a `Progress`, from `Progress::start_jobs`.
+#[derive(Debug)]
+pub struct Jobs {
+  progress: &'static Progress,
+}
+
+impl Jobs {
+  /// Count one more job as done.
+  pub fn finish(&self) {
+    self.progress.done.fetch_add(1, Ordering::Relaxed);
+  }
+}
+
+impl Drop for Jobs {
+  fn drop(&mut self) {
+    self.progress.jobs.store(false, Ordering::Relaxed);
+  }
+}
+
+/// A rectangular chunk of the image, rendered as a unit by one worker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+  pub x: u32,
+  pub y: u32,
+  pub width: u32,
+  pub height: u32,
+}
+
+impl Tile {
+  /// The tile covering an entire `width` by `height` image.
+  pub fn whole(width: u32, height: u32) -> Tile {
+    Tile { x: 0, y: 0, width, height }
+  }
+}
+
+/// Renders images from a per-pixel (or per-tile) function, in parallel.
+///
+/// Tiles are square by default, since neighboring pixels in both directions tend to touch the same noise cells and
+/// source pixels. Set the tile width to the image width to render in full-width row bands instead.
+#[derive(Debug, Clone)]
+pub struct Renderer {
+  tile_width: u32,
+  tile_height: u32,
+  quiet: bool,
+}
+
+impl Default for Renderer {
+  fn default() -> Renderer {
+    Renderer { tile_width: 64, tile_height: 64, quiet: false }
+  }
+}
+
+impl Renderer {
+  pub fn new() -> Renderer {
+    Renderer::default()
+  }
+
+  /// Set the size of the tiles the image is split into. Tiles at the right and bottom edges may be smaller.
+  pub fn tile_size(mut self, width: u32, height: u32) -> Renderer {
+    assert!(width > 0 && height > 0, "tiles must have some area");
+    self.tile_width = width;
+    self.tile_height = height;
+    self
+  }
+
+  pub fn tile_height(&self) -> u32 {
+    self.tile_height
+  }
+
+  /// Don't log how long rendering took, or restart the progress count. For when rendering is just part of a bigger
+  /// job, which is timed and counted as a whole.
+  pub fn quiet(mut self) -> Renderer {
+    self.quiet = true;
+    self
+  }
+
+  /// Split a `width` by `height` image into tiles, in row-major order.
+  pub fn tiles(&self, width: u32, height: u32) -> Vec<Tile> {
+    self.tiles_in(Tile::whole(width, height))
+  }
+
+  /// Split part of an image into tiles, in row-major order. The tiles' coordinates are still relative to the whole
+  /// image, not the area.
+  pub fn tiles_in(&self, area: Tile) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    for y in (area.y..area.y + area.height).step_by(self.tile_height as usize) {
+      for x in (area.x..area.x + area.width).step_by(self.tile_width as usize) {
+        tiles.push(Tile {
+          x,
+          y,
+          width: self.tile_width.min(area.x + area.width - x),
+          height: self.tile_height.min(area.y + area.height - y),
+        });
+      }
+    }
+    tiles
+  }
+
+  /// Render an image by calling `pixel` with the coordinates of every pixel.
+  pub fn render<P, F>(&self, width: u32, height: u32, pixel: F) -> ImageBuffer<P, Vec<P::Subpixel>>
+  where
+    P: Pixel + 'static,
+    P::Subpixel: Default + Send + Sync,
+    F: Fn(u32, u32) -> P + Sync,
+  {
+    self.render_area(Tile::whole(width, height), pixel)
+  }
+
+  /// Render just part of an image. `pixel` is still called with coordinates in the whole image, but the result only
+  /// covers the area, with its top-left pixel at (0, 0).
+  pub fn render_area<P, F>(&self, area: Tile, pixel: F) -> ImageBuffer<P, Vec<P::Subpixel>>
+  where
+    P: Pixel + 'static,
+    P::Subpixel: Default + Send + Sync,
+    F: Fn(u32, u32) -> P + Sync,
+  {
+    self.render_area_tiles(area, |tile, data| {
+      let channels = P::CHANNEL_COUNT as usize;
+      for dy in 0..tile.height {
+        for dx in 0..tile.width {
+          let idx = (dy * tile.width + dx) as usize * channels;
+          let px = pixel(tile.x + dx, tile.y + dy);
+          data[idx..idx + channels].copy_from_slice(px.channels());
+        }
+      }
+    })
+  }
+
+  /// Render an image tile by tile. `fill` is given each tile and a buffer for just that tile's subpixels, row-major,
+  /// to fill in however it likes. Useful when there's per-tile setup worth sharing across pixels.
+  pub fn render_tiles<P, F>(&self, width: u32, height: u32, fill: F) -> ImageBuffer<P, Vec<P::Subpixel>>
+  where
+    P: Pixel + 'static,
+    P::Subpixel: Default + Send + Sync,
+    F: Fn(Tile, &mut [P::Subpixel]) + Sync,
+  {
+    self.render_area_tiles(Tile::whole(width, height), fill)
+  }
+
+  /// Render part of an image tile by tile, like `render_tiles` but with the tiles limited to (and the result only
+  /// covering) `area`.
+  pub fn render_area_tiles<P, F>(&self, area: Tile, fill: F) -> ImageBuffer<P, Vec<P::Subpixel>>
+  where
+    P: Pixel + 'static,
+    P::Subpixel: Default + Send + Sync,
+    F: Fn(Tile, &mut [P::Subpixel]) + Sync,
+  {
+    let channels = P::CHANNEL_COUNT as usize;
+    let zero = P::Subpixel::default();
+
+    let _span = if self.quiet {
+      None
+    } else {
+      Progress::global().start(area.width as u64 * area.height as u64);
+      Some(Span::enter("generate"))
+    };
+    let rendered: Vec<(Tile, Vec<P::Subpixel>)> = self.tiles_in(area)
+      .into_par_iter()
+      .map(|tile| {
+        let mut data = vec![zero; tile.width as usize * tile.height as usize * channels];
+        fill(tile, &mut data);
+        Progress::global().finish(tile.width as u64 * tile.height as u64);
+        (tile, data)
+      })
+      .collect();
+    drop(_span);
+
+    let row_len = area.width as usize * channels;
+    let mut pixels = vec![zero; row_len * area.height as usize];
+    for (tile, data) in rendered {
+      let tile_row_len = tile.width as usize * channels;
+      for (dy, row) in data.chunks(tile_row_len).enumerate() {
+        let start = (tile.y - area.y) as usize * row_len + dy * row_len + (tile.x - area.x) as usize * channels;
+        pixels[start..start + tile_row_len].copy_from_slice(row);
+      }
+    }
+    ImageBuffer::from_raw(area.width, area.height, pixels).expect("rendered exactly enough pixels")
+  }
+}
+
+/// Which part of a raster to render, and at what size. Pixel (x, y) of the output shows the raster at
+/// (x * scale, y * scale), and `area` is the part of that (scaled) image to render. So a scale of 1 renders the raster
+/// as it is, and a scale of 4 renders it at a quarter of the size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct View {
+  pub area: Tile,
+  pub scale: f32,
+}
+
+impl View {
+  /// The whole of a `width` by `height` raster, at full size.
+  pub fn whole(width: u32, height: u32) -> View {
+    View { area: Tile::whole(width, height), scale: 1.0 }
+  }
+
+  /// Part of a raster, at full size.
+  pub fn region(area: Tile) -> View {
+    View { area, scale: 1.0 }
+  }
+
+  /// One tile of a slippy map pyramid over a `width` by `height` canvas. Zoom level 0 fits the whole canvas into one
+  /// `size`-pixel tile, and every level after that doubles the number of tiles across and down.
+  pub fn slippy(width: u32, height: u32, size: u32, zoom: u32, x: u32, y: u32) -> View {
+    let scale = width.max(height) as f32 / (size as f32 * (1u64 << zoom) as f32);
+    View { area: Tile { x: x * size, y: y * size, width: size, height: size }, scale }
+  }
+
+  /// The same view at 1/`factor` of the size, sampling every `factor`th of the same points, for a quick preview.
+  pub fn shrink(self, factor: u32) -> View {
+    let View { area, scale } = self;
+    // only the points inside the original area are sampled, with at least one across and down
+    let (x, y) = (area.x.div_ceil(factor), area.y.div_ceil(factor));
+    let width = ((area.x + area.width).div_ceil(factor) - x).max(1);
+    let height = ((area.y + area.height).div_ceil(factor) - y).max(1);
+    View { area: Tile { x, y, width, height }, scale: scale * factor as f32 }
+  }
+
+  /// How many slippy map tiles across and down it takes to cover a `width` by `height` canvas at some zoom level.
+  /// Only the longer side is covered by exactly `2^zoom` tiles; the shorter one needs however many reach its edge.
+  pub fn slippy_count(width: u32, height: u32, zoom: u32) -> (u32, u32) {
+    let across = 1u32 << zoom;
+    let longest = width.max(height) as u64;
+    let count = |side: u32| (side as u64 * across as u64).div_ceil(longest) as u32;
+    (count(width), count(height))
+  }
+}
+
+/// An image that can be rendered a piece at a time, so the whole thing never has to be in memory at once.
+pub trait Raster: Sync {
+  /// The size of the image at full scale.
+  fn dimensions(&self) -> (u32, u32);
+  /// Whether the image carries on past its dimensions, like noise which is defined everywhere. Only unbounded rasters
+  /// can be rendered outside of their dimensions.
+  fn unbounded(&self) -> bool;
+  /// Render one view of the image, with its top-left pixel at (0, 0).
+  fn render(&self, renderer: &Renderer, view: View) -> Image;
+}
+
+impl dyn Raster + '_ {
+  /// Whether every point a view would sample is within the raster: always true if it's unbounded.
+  pub fn covers(&self, view: View) -> bool {
+    let (width, height) = self.dimensions();
+    let View { area, scale } = view;
+    let last = |start: u32, len: u32| (start + len).saturating_sub(1) as f32 * scale;
+    self.unbounded() || (last(area.x, area.width) < width as f32 && last(area.y, area.height) < height as f32)
+  }
+
+  /// Render the whole image at once.
+  pub fn render_all(&self, renderer: &Renderer) -> Image {
+    let (width, height) = self.dimensions();
+    self.render(renderer, View::whole(width, height))
+  }
+}
+
+/// A `Raster` from a function giving the pixel at each point, which is what most generators boil down to.
+/// Points are at the raster's full scale, and will have fractional parts if it's being rendered at some other scale.
+pub struct Pixels<P, F> {
+  width: u32,
+  height: u32,
+  unbounded: bool,
+  pixel: F,
+  // fn() -> P so Pixels is Sync whether or not P is
+  _pixel: PhantomData<fn() -> P>,
+}
+
+impl<P, F> Pixels<P, F> {
+  pub fn new(width: u32, height: u32, pixel: F) -> Pixels<P, F> {
+    Pixels { width, height, unbounded: false, pixel, _pixel: PhantomData }
+  }
+
+  /// Mark the function as valid everywhere, not just inside the dimensions.
+  pub fn unbounded(mut self) -> Pixels<P, F> {
+    self.unbounded = true;
+    self
+  }
+}
+
+impl<P, F> Raster for Pixels<P, F>
+where
+  P: Pixel + 'static,
+  P::Subpixel: Default + Send + Sync,
+  F: Fn(f32, f32) -> P + Sync,
+  ImageBuffer<P, Vec<P::Subpixel>>: Into<Image>,
+{
+  fn dimensions(&self) -> (u32, u32) {
+    (self.width, self.height)
+  }
+
+  fn unbounded(&self) -> bool {
+    self.unbounded
+  }
+
+  fn render(&self, renderer: &Renderer, view: View) -> Image {
+    // the output pixel coordinates go through f32 either way, so at a scale of 1 this is exactly the same as rendering
+    // the whole image and cropping it
+    renderer.render_area(view.area, |x, y| (self.pixel)(x as f32 * view.scale, y as f32 * view.scale)).into()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    image::{Luma, Rgb},
+  };
+
+  #[test]
+  fn tiles_cover_the_image_exactly() {
+    let tiles = Renderer::new().tile_size(16, 10).tiles(50, 25);
+    let mut covered = vec![0; 50 * 25];
+    for tile in tiles {
+      for y in tile.y..tile.y + tile.height {
+        for x in tile.x..tile.x + tile.width {
+          covered[(y * 50 + x) as usize] += 1;
+        }
+      }
+    }
+    assert!(covered.iter().all(|&c| c == 1));
+  }
+
+  #[test]
+  fn pixels_land_in_the_right_place() {
+    for &(tw, th) in [(1, 1), (7, 3), (64, 64), (100, 1)].iter() {
+      let img = Renderer::new().tile_size(tw, th).quiet().render(37, 19, |x, y| Rgb([x as u8, y as u8, 7]));
+      for (x, y, px) in img.enumerate_pixels() {
+        assert_eq!(px.0, [x as u8, y as u8, 7], "wrong pixel with {}x{} tiles", tw, th);
+      }
+    }
+  }
+
+  #[test]
+  fn areas_match_the_whole_image() {
+    let pixel = |x: u32, y: u32| Luma([(x * 31 + y * 17) as u16]);
+    let whole = Renderer::new().tile_size(8, 8).quiet().render(40, 30, pixel);
+    let area = Tile { x: 5, y: 11, width: 21, height: 13 };
+    let part = Renderer::new().tile_size(8, 8).quiet().render_area(area, pixel);
+    assert_eq!(part.dimensions(), (21, 13));
+    for (x, y, px) in part.enumerate_pixels() {
+      assert_eq!(px, whole.get_pixel(x + 5, y + 11));
+    }
+  }
+
+  #[test]
+  fn slippy_tiles_cover_the_canvas() {
+    assert_eq!(View::slippy_count(1000, 1000, 0), (1, 1));
+    assert_eq!(View::slippy_count(1000, 1000, 3), (8, 8));
+    assert_eq!(View::slippy_count(1000, 300, 2), (4, 2));
+    // the bottom-right tile at each level starts inside the canvas
+    for zoom in 0..6 {
+      let (across, down) = View::slippy_count(1000, 300, zoom);
+      let last = View::slippy(1000, 300, 256, zoom, across - 1, down - 1);
+      assert!((last.area.x as f32 * last.scale) < 1000.0);
+      assert!((last.area.y as f32 * last.scale) < 300.0);
+      let first = View::slippy(1000, 300, 256, zoom, 0, 0);
+      assert_eq!(first.scale * 256.0 * (1 << zoom) as f32, 1000.0);
+    }
+  }
+
+  #[test]
+  fn scaled_views_sample_the_same_points() {
+    let raster = Pixels::new(40, 40, |x: f32, y: f32| Luma([x + y * 1000.0])).unbounded();
+    let raster: &dyn Raster = &raster;
+    let full = raster.render_all(&Renderer::new().quiet());
+    let half = raster.render(&Renderer::new().quiet(), View { area: Tile::whole(20, 20), scale: 2.0 });
+    match (full, half) {
+      (Image::LumaF32(full), Image::LumaF32(half)) => {
+        for (x, y, px) in half.enumerate_pixels() {
+          assert_eq!(px, full.get_pixel(x * 2, y * 2));
+        }
+      }
+      _ => panic!("rendered the wrong kind of image"),
+    }
+  }
+
+  #[test]
+  fn shrunk_views_stay_inside_the_original() {
+    assert_eq!(View::whole(100, 50).shrink(4), View { area: Tile::whole(25, 13), scale: 4.0 });
+    let region = View::region(Tile { x: 10, y: 3, width: 7, height: 1 }).shrink(4);
+    assert_eq!(region.area, Tile { x: 3, y: 1, width: 2, height: 1 });
+    // the first and last points sampled are still in the region
+    assert!(region.area.x as f32 * region.scale >= 10.0);
+    assert!((region.area.x + region.area.width - 1) as f32 * region.scale < 17.0);
+    assert_eq!(View::whole(10, 10).shrink(1), View::whole(10, 10));
+  }
+
+  #[test]
+  fn tiles_get_their_own_buffers() {
+    let img = Renderer::new().tile_size(4, 4).quiet().render_tiles::<Luma<u16>, _>(10, 10, |tile, data| {
+      assert_eq!(data.len(), (tile.width * tile.height) as usize);
+      for v in data.iter_mut() {
+        *v = (tile.y * 10 + tile.x) as u16;
+      }
+    });
+    assert_eq!(img.get_pixel(9, 9).0, [88]);
+    assert_eq!(img.get_pixel(5, 1).0, [4]);
+  }
+}
@@ -0,0 +1,31 @@
+//! Timing the phases of a run, like decoding inputs, generating, and encoding output.
+
+use std::time::Instant;
+
+/// Logs how long a phase took once it's over, i.e. when the span is dropped. Timings are debug messages, so they're
+/// only shown with `-v`, and when each phase starts is a trace message, for `-vv`.
+///
+/// ```
+/// # use hart::utils::Span;
+/// let _span = Span::enter("encode");
+/// // ...encode things...
+/// // logs "encode took 12ms" here
+/// ```
+#[derive(Debug)]
+pub struct Span {
+  phase: &'static str,
+  start: Instant,
+}
+
+impl Span {
+  pub fn enter(phase: &'static str) -> Span {
+    log::trace!("Starting to {}", phase);
+    Span { phase, start: Instant::now() }
+  }
+}
+
+impl Drop for Span {
+  fn drop(&mut self) {
+    log::debug!("{} took {}ms", self.phase, self.start.elapsed().as_millis());
+  }
+}
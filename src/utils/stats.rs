@@ -0,0 +1,129 @@
+//! Statistical tests for checking that random-looking output actually looks random.
+//! These aren't anywhere near a proper test battery like TestU01 or PractRand, but they're cheap enough to run on
+//! every `cargo test` and they catch the obvious regressions: biased bytes, stuck bits, repeated blocks, etc.
+//!
+//! Each test returns a statistic which should be "small" for random data. The thresholds are picked so that a good
+//! generator essentially never fails them with the fixed seeds the tests use, while a broken one fails loudly.
+
+/// Pearson's chi-square statistic for the byte distribution of `data`, against a uniform distribution.
+/// With 255 degrees of freedom, anything above ~330 has less than a 0.1% chance of happening by accident.
+pub fn chi_square_bytes(data: &[u8]) -> f64 {
+  let mut counts = [0usize; 256];
+  for byte in data {
+    counts[*byte as usize] += 1;
+  }
+  let expected = data.len() as f64 / 256.0;
+  counts.iter()
+    .map(|&count| {
+      let diff = count as f64 - expected;
+      diff * diff / expected
+    })
+    .sum()
+}
+
+/// Knuth's serial correlation coefficient: how well each byte predicts the next.
+/// Should be close to 0; totally predictable data gives 1 (or -1).
+pub fn serial_correlation(data: &[u8]) -> f64 {
+  let n = data.len() as f64;
+  let mut sum = 0.0;
+  let mut sum_sq = 0.0;
+  let mut sum_pairs = 0.0;
+  for (i, &byte) in data.iter().enumerate() {
+    let this = byte as f64;
+    let next = data[(i + 1) % data.len()] as f64;
+    sum += this;
+    sum_sq += this * this;
+    sum_pairs += this * next;
+  }
+  let top = n * sum_pairs - sum * sum;
+  let bottom = n * sum_sq - sum * sum;
+  if bottom == 0.0 {
+    // every byte is identical, which is about as correlated as it gets
+    1.0
+  } else {
+    top / bottom
+  }
+}
+
+/// The z-score of the number of ones among all the bits of `data` (the "monobit" test).
+/// Roughly standard normal for random data, so anything much past 4 is suspicious.
+pub fn bit_frequency(data: &[u8]) -> f64 {
+  let bits = data.len() as f64 * 8.0;
+  let ones: u32 = data.iter().map(|byte| byte.count_ones()).sum();
+  (ones as f64 - bits / 2.0) / (bits / 4.0).sqrt()
+}
+
+/// The z-score of the number of runs (maximal sequences of identical bits) in `data`, per the Wald-Wolfowitz test.
+/// Catches data with the right number of ones, but in the wrong order, e.g. `0xF0` repeated forever.
+pub fn runs(data: &[u8]) -> f64 {
+  let bit = |i: usize| (data[i / 8] >> (i % 8)) & 1;
+  let total = data.len() * 8;
+  let ones = data.iter().map(|byte| byte.count_ones() as usize).sum::<usize>() as f64;
+  let zeroes = total as f64 - ones;
+  let mut count = 1.0;
+  for i in 1..total {
+    if bit(i) != bit(i - 1) {
+      count += 1.0;
+    }
+  }
+  let n = total as f64;
+  let mean = 2.0 * ones * zeroes / n + 1.0;
+  let variance = (mean - 1.0) * (mean - 2.0) / (n - 1.0);
+  (count - mean) / variance.sqrt()
+}
+
+/// The mean and (population) variance of some samples.
+pub fn mean_variance(samples: &[f32]) -> (f64, f64) {
+  let n = samples.len() as f64;
+  let mean = samples.iter().map(|&s| s as f64).sum::<f64>() / n;
+  let variance = samples.iter().map(|&s| (s as f64 - mean).powi(2)).sum::<f64>() / n;
+  (mean, variance)
+}
+
+/// Run every byte-level test over `data` and panic with a useful message if any of them fail.
+/// `what` is used to identify the data in the failure message.
+pub fn assert_random(what: &str, data: &[u8]) {
+  let chi = chi_square_bytes(data);
+  assert!(chi < 340.0, "{}: chi-square of {} is too high", what, chi);
+  let corr = serial_correlation(data);
+  assert!(corr.abs() < 0.01, "{}: serial correlation of {} is too high", what, corr);
+  let freq = bit_frequency(data);
+  assert!(freq.abs() < 4.5, "{}: bit frequency z-score of {} is too extreme", what, freq);
+  let runs = runs(data);
+  assert!(runs.abs() < 4.5, "{}: runs z-score of {} is too extreme", what, runs);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Make sure the tests themselves actually notice bad data, or the rest of the suite is meaningless.
+
+  #[test]
+  fn chi_square_catches_bias() {
+    let biased: Vec<u8> = (0..65536).map(|i| (i % 128) as u8).collect();
+    assert!(chi_square_bytes(&biased) > 1000.0);
+    let flat: Vec<u8> = (0..65536).map(|i| i as u8).collect();
+    assert!(chi_square_bytes(&flat) < 1.0);
+  }
+
+  #[test]
+  fn serial_correlation_catches_ramps() {
+    let ramp: Vec<u8> = (0..65536).map(|i| (i / 256) as u8).collect();
+    assert!(serial_correlation(&ramp) > 0.9);
+    assert_eq!(serial_correlation(&[7; 100]), 1.0);
+  }
+
+  #[test]
+  fn bit_frequency_catches_stuck_bits() {
+    assert!(bit_frequency(&[0x01; 4096]) < -50.0);
+    assert!(bit_frequency(&[0x55; 4096]).abs() < 0.001);
+  }
+
+  #[test]
+  fn runs_catches_patterns() {
+    // right number of ones, wrong order
+    assert!(runs(&[0x55; 4096]) > 50.0);
+    assert!(runs(&[0xF0; 4096]) < -50.0);
+  }
+}
@@ -0,0 +1,257 @@
+//! Drive the generators through the library, the way other tools embedding hart would.
+
+use {
+  hart::{output::{self, Output}, params, utils::render::{Renderer, Tile, View}, Gen, GenFail, Registry},
+  image::{GenericImageView as _, RgbImage},
+  std::path::PathBuf,
+};
+
+/// Run a generator with some parameters, given as text, and return its output.
+fn run(category: &str, command: &str, given: &[(&str, &str)], seed: &[u8]) -> hart::gens::Result<Output> {
+  let gen = Registry::builtin().find(category, command).expect("generator should exist");
+  let params = params::resolve(&gen.params(), given.iter().cloned()).expect("parameters should be valid");
+  gen.run(&params, seed)
+}
+
+/// Run a generator whose output is an image.
+fn run_image(category: &str, command: &str, given: &[(&str, &str)], seed: &[u8]) -> image::DynamicImage {
+  match run(category, command, given, seed).unwrap() {
+    Output::Image(img) => img.to_dynamic(),
+    Output::Raw(_) => panic!("{} should output an image", command),
+  }
+}
+
+/// Write a solid-color image into the test scratch directory, returning its path.
+fn solid_image(name: &str, width: u32, height: u32, color: [u8; 3]) -> String {
+  let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+  RgbImage::from_pixel(width, height, image::Rgb(color)).save(&path).unwrap();
+  path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn every_gen_can_be_found() {
+  let registry = Registry::builtin();
+  for gen in registry.all() {
+    let found = registry.find(gen.category().name(), gen.command()).unwrap();
+    assert_eq!(found.command(), gen.command());
+    for alias in gen.aliases() {
+      assert_eq!(registry.find(gen.category().name(), alias).unwrap().command(), gen.command());
+    }
+  }
+  assert!(registry.find("basic", "nonexistent").is_none());
+  assert!(registry.find("nonexistent", "worley").is_none());
+}
+
+#[test]
+fn registry_refuses_clashing_names() {
+  struct Impostor;
+  impl Gen for Impostor {
+    fn category(&self) -> hart::Category { hart::Category::Basic }
+    fn command(&self) -> &'static str { "impostor" }
+    fn about(&self) -> &'static str { "Claims to be Worley noise" }
+    fn aliases(&self) -> &'static [&'static str] { &["cellular"] }
+    fn params(&self) -> Vec<params::Param> { vec![] }
+  }
+  let mut registry = Registry::builtin();
+  assert!(registry.register(&Impostor).is_err());
+  assert!(registry.find("basic", "impostor").is_none());
+  assert_eq!(Registry::new().with(&Impostor).tagged("noise").count(), 0);
+}
+
+#[test]
+fn every_gen_has_valid_defaults() {
+  for gen in Registry::builtin().all() {
+    for param in gen.params() {
+      if let Some(default) = param.default {
+        assert!(param.parse(default).is_ok(), "{} has an invalid default for {}", gen.command(), param.name);
+      }
+      assert!(!params::RESERVED.contains(&param.name), "{} reuses a reserved name", gen.command());
+      let short = param.short.unwrap_or(' ');
+      assert!(!params::RESERVED_SHORTS.contains(&short), "{} reuses a reserved short", gen.command());
+    }
+  }
+}
+
+#[test]
+fn ascii_depends_only_on_seed() {
+  let a = run("test", "ascii", &[], b"seed").unwrap();
+  assert_eq!(a, run("test", "ascii", &[], b"seed").unwrap());
+  assert_ne!(a, run("test", "ascii", &[], b"other seed").unwrap());
+}
+
+#[test]
+fn worley_makes_requested_image() {
+  let given = [("size", "40x30"), ("octaves", "2")];
+  let img = run_image("basic", "worley", &given, b"seed");
+  assert_eq!(img.dimensions(), (40, 30));
+  assert_eq!(img.color(), image::ColorType::L16);
+  assert_eq!(img, run_image("basic", "worley", &given, b"seed"));
+  assert_ne!(img, run_image("basic", "worley", &given, b"other seed"));
+}
+
+#[test]
+fn images_encode_as_png() {
+  let out = run("basic", "worley", &[("size", "40x30")], b"seed").unwrap();
+  let mut png = vec![];
+  output::write(&out, None, &output::Encoding::default(), &mut png).unwrap();
+  assert_eq!(image::guess_format(&png).unwrap(), image::ImageFormat::Png);
+  let expected = match out {
+    Output::Image(img) => img.to_depth(output::Depth::Eight).to_dynamic(),
+    Output::Raw(_) => panic!("worley should output an image"),
+  };
+  assert_eq!(image::load_from_memory(&png).unwrap(), expected);
+}
+
+#[test]
+fn worley_keeps_full_precision() {
+  match run("basic", "worley", &[("size", "40x30")], b"seed").unwrap() {
+    Output::Image(img) => {
+      assert_eq!(img.depth(), output::Depth::Float);
+      let sixteen = img.to_depth(output::Depth::Sixteen).to_dynamic().to_luma16();
+      // 8 bits would only ever give multiples of 257
+      assert!(sixteen.pixels().any(|px| px.0[0] % 257 != 0));
+    }
+    Output::Raw(_) => panic!("worley should output an image"),
+  }
+}
+
+#[test]
+fn mottle_blends_from_one_image_to_the_other() {
+  let black = solid_image("mottle-black.png", 40, 20, [0, 0, 0]);
+  let white = solid_image("mottle-white.png", 40, 20, [255, 255, 255]);
+  let img = run_image("project", "mottle", &[("from", &black), ("to", &white)], b"seed").to_rgb8();
+  assert_eq!(img.dimensions(), (40, 20));
+  for y in 0..20 {
+    assert_eq!(img.get_pixel(0, y).0, [0, 0, 0]);
+    assert_eq!(img.get_pixel(39, y).0, [255, 255, 255]);
+  }
+}
+
+#[test]
+fn mottle_goes_whichever_way_it_is_told() {
+  let black = solid_image("mottle-way-black.png", 40, 40, [0, 0, 0]);
+  let white = solid_image("mottle-way-white.png", 40, 40, [255, 255, 255]);
+  let mottle = |given: &[(&str, &str)]| {
+    let images = [("from", black.as_str()), ("to", white.as_str())];
+    run_image("project", "mottle", &[&images[..], given].concat(), b"seed").to_rgb8()
+  };
+
+  let vertical = mottle(&[("direction", "vertical")]);
+  assert_eq!(vertical.get_pixel(20, 0).0, [0, 0, 0]);
+  assert_eq!(vertical.get_pixel(20, 39).0, [255, 255, 255]);
+
+  // right to left
+  let angled = mottle(&[("direction", "angle"), ("angle", "180")]);
+  assert_eq!(angled.get_pixel(39, 20).0, [0, 0, 0]);
+  assert_eq!(angled.get_pixel(0, 20).0, [255, 255, 255]);
+
+  let radial = mottle(&[("direction", "radial"), ("center", "25%,25%")]);
+  assert_eq!(radial.get_pixel(10, 10).0, [0, 0, 0]);
+  assert_eq!(radial.get_pixel(39, 39).0, [255, 255, 255]);
+
+  let path = mottle(&[("direction", "path"), ("path", "0%,50%;100%,50%")]);
+  assert_eq!(path.get_pixel(20, 20).0, [0, 0, 0]);
+  assert_eq!(path.get_pixel(20, 0).0, [255, 255, 255]);
+
+  // every corner is on the path, so it's the middle that's farthest from it
+  let border = mottle(&[("direction", "path"), ("path", "0%,0%;100%,0%;100%,100%;0%,100%;0%,0%")]);
+  for edge in [(0, 0), (20, 0), (39, 20), (20, 39), (0, 20)] {
+    assert_eq!(border.get_pixel(edge.0, edge.1).0, [0, 0, 0]);
+  }
+  assert_eq!(border.get_pixel(20, 20).0, [255, 255, 255]);
+
+  // starting and ending in the same place switches straight from one image to the other
+  let step = mottle(&[("start", "50%"), ("end", "50%"), ("sharp", "true")]);
+  assert_eq!(step.get_pixel(0, 20).0, [0, 0, 0]);
+  assert_eq!(step.get_pixel(39, 20).0, [255, 255, 255]);
+
+  let err = run("project", "mottle", &[("from", &black), ("to", &white), ("direction", "path")], b"seed").unwrap_err();
+  assert!(matches!(err.root(), GenFail::BadArg(_)));
+}
+
+#[test]
+fn mottle_sniffs_input_formats() {
+  // PNGs, whatever their names say
+  let black = solid_image("mottle-sniff-black.png", 40, 20, [0, 0, 0]);
+  let white = solid_image("mottle-sniff-white.png", 40, 20, [255, 255, 255]);
+  let (black_jpg, white_bmp) = (black.replace(".png", ".jpg"), white.replace(".png", ".bmp"));
+  std::fs::rename(&black, &black_jpg).unwrap();
+  std::fs::rename(&white, &white_bmp).unwrap();
+  let img = run_image("project", "mottle", &[("from", &black_jpg), ("to", &white_bmp)], b"seed").to_rgb8();
+  assert_eq!(img.get_pixel(0, 0).0, [0, 0, 0]);
+  assert_eq!(img.get_pixel(39, 0).0, [255, 255, 255]);
+}
+
+#[test]
+fn mottle_rejects_mismatched_images() {
+  let small = solid_image("mottle-small.png", 10, 10, [0, 0, 0]);
+  let big = solid_image("mottle-big.png", 20, 10, [0, 0, 0]);
+  match run("project", "mottle", &[("from", &small), ("to", &big)], b"seed") {
+    Err(GenFail::Data(_)) => (),
+    other => panic!("expected a Data failure, got {:?}", other.map(|_| ())),
+  }
+}
+
+#[test]
+fn mottle_doesnt_offer_perlin_noise_yet() {
+  let gen = Registry::builtin().find("project", "mottle").unwrap();
+  let given = [("from", "a.png"), ("to", "b.png"), ("algorithm", "perlin")];
+  let err = params::resolve(&gen.params(), given).unwrap_err();
+  assert!(err.contains("perlin"), "{}", err);
+}
+
+#[test]
+fn failures_say_which_file() {
+  use std::error::Error as _;
+  let black = solid_image("mottle-exists.png", 10, 10, [0, 0, 0]);
+  let err = run("project", "mottle", &[("from", &black), ("to", "no/such/image.png")], b"seed").unwrap_err();
+  assert_eq!(err.to_string(), "couldn't open no/such/image.png");
+  assert!(matches!(err.root(), GenFail::Io(_)));
+  assert!(err.source().is_some());
+}
+
+#[test]
+fn noise_and_rng_are_usable_directly() {
+  use {
+    hart::{noise::{Noise2D as _, Pos, Worley}, ForeveRNG},
+    rand::{Rng as _, SeedableRng as _},
+  };
+  let mut rng = ForeveRNG::seed_from_u64(5);
+  let roll = rng.gen_range(1..=6);
+  assert!((1..=6).contains(&roll));
+  let noise = Worley::from_rng(rng.derive("noise"));
+  let sample = noise.get(Pos::of(1.5, -2.5));
+  assert!((0.0..=1.0).contains(&sample));
+}
+
+#[test]
+fn streaming_matches_running() {
+  let gen = Registry::builtin().find("basic", "worley").unwrap();
+  let params = params::resolve(&gen.params(), [("size", "90x200")].iter().cloned()).unwrap();
+  let raster = gen.raster(&params, b"seed").unwrap().expect("worley should be a raster");
+  let recipe = hart::recipe::Recipe {
+    version: hart::VERSION.to_string(),
+    generator: "basic/worley".to_string(),
+    params: params.to_text(),
+    seed: b"seed".to_vec(),
+    view: None,
+  };
+  let mut png = vec![];
+  output::stream(&*raster, View::whole(90, 200), Some(&recipe), &output::Encoding::default(), &mut png).unwrap();
+  assert_eq!(hart::recipe::Recipe::extract(&png).unwrap(), recipe);
+
+  let mut whole = vec![];
+  output::write(&gen.run(&params, b"seed").unwrap(), None, &output::Encoding::default(), &mut whole).unwrap();
+  assert_eq!(image::load_from_memory(&png).unwrap(), image::load_from_memory(&whole).unwrap());
+}
+
+#[test]
+fn regions_match_crops_of_the_whole() {
+  let gen = Registry::builtin().find("basic", "worley").unwrap();
+  let params = params::resolve(&gen.params(), [("size", "120x80")].iter().cloned()).unwrap();
+  let raster = gen.raster(&params, b"seed").unwrap().expect("worley should be a raster");
+  let whole = raster.render_all(&Renderer::new().quiet()).to_dynamic();
+  let area = Tile { x: 37, y: 11, width: 50, height: 61 };
+  let region = raster.render(&Renderer::new().quiet(), View::region(area)).to_dynamic();
+  assert_eq!(region, whole.crop_imm(area.x, area.y, area.width, area.height));
+}
@@ -0,0 +1,136 @@
+//! Read job files the way `hart run` does, without running anything.
+
+use {
+  hart::{jobs, output::Format, seed, Registry},
+  std::path::{Path, PathBuf},
+};
+
+fn parse(contents: &str) -> Result<Vec<jobs::Job>, String> {
+  jobs::parse(contents, Path::new("session"), &Registry::builtin())
+}
+
+#[test]
+fn jobs_start_from_the_defaults() {
+  let jobs = parse(r#"
+    [defaults]
+    format = "tiff"
+    params = { size = [40, 30], octaves = 2 }
+
+    [[job]]
+    name = "cells"
+    generator = "basic/cellular"
+    seed = "exhibition"
+    output = "out/cells.tif"
+    params = { octaves = 3 }
+
+    [[job]]
+    generator = "project/mottle"
+    seed-int = 4
+    format = "png"
+    output = "/tmp/blend.png"
+    params = { from = "in/a.png", to = "out/cells.tif", sharp = true }
+  "#).unwrap();
+  assert_eq!(jobs.len(), 2);
+
+  let cells = &jobs[0];
+  assert_eq!(cells.name, "cells");
+  assert_eq!(cells.recipe.generator, "basic/cellular");
+  assert_eq!(cells.recipe.seed, b"exhibition");
+  assert_eq!(cells.encoding.format, Format::Tiff);
+  assert_eq!(cells.output, PathBuf::from("session/out/cells.tif"));
+  assert_eq!(cells.params.int("octaves"), 3);
+  assert_eq!(cells.params.size("size"), (40, 30));
+
+  // mottle doesn't take a size or octaves, so it doesn't get the defaults for them
+  let blend = &jobs[1];
+  assert_eq!(blend.name, "job 2");
+  assert_eq!(blend.recipe.seed, seed::from_int("4").unwrap());
+  assert_eq!(blend.encoding.format, Format::Png);
+  assert_eq!(blend.output, PathBuf::from("/tmp/blend.png"));
+  assert!(blend.params.flag("sharp"));
+  assert_eq!(blend.inputs, [PathBuf::from("session/in/a.png"), PathBuf::from("session/out/cells.tif")]);
+}
+
+#[test]
+fn jobs_wait_for_their_inputs() {
+  let jobs = parse(r#"
+    [[job]]
+    generator = "project/mottle"
+    seed = "a"
+    output = "second.png"
+    params = { from = "first.png", to = "first.png" }
+
+    [[job]]
+    generator = "basic/worley"
+    seed = "b"
+    output = "first.png"
+    params = { size = [4, 4] }
+
+    [[job]]
+    generator = "test/2d"
+    seed = "c"
+    output = "alone.png"
+  "#).unwrap();
+  assert_eq!(jobs::stages(&jobs).unwrap(), [vec![1, 2], vec![0]]);
+}
+
+#[test]
+fn impossible_orders_are_caught() {
+  let cycle = parse(r#"
+    [[job]]
+    name = "chicken"
+    generator = "project/mottle"
+    seed = "a"
+    output = "chicken.png"
+    params = { from = "egg.png", to = "egg.png" }
+
+    [[job]]
+    name = "egg"
+    generator = "project/mottle"
+    seed = "a"
+    output = "egg.png"
+    params = { from = "chicken.png", to = "chicken.png" }
+  "#).unwrap();
+  assert_eq!(jobs::stages(&cycle).unwrap_err(), "chicken, egg need each other's outputs, so none of them can go first");
+
+  let clash = parse(r#"
+    [[job]]
+    generator = "test/2d"
+    seed = "a"
+    output = "same.png"
+
+    [[job]]
+    generator = "test/2d"
+    seed = "b"
+    output = "same.png"
+  "#).unwrap();
+  assert_eq!(jobs::stages(&clash).unwrap_err(), "job 1 and job 2 both write session/same.png");
+}
+
+#[test]
+fn mistakes_say_which_job() {
+  let job = |body: &str| parse(&format!("[[job]]\n{}", body)).err().unwrap();
+  assert_eq!(job("generator = 'test/2d'\noutput = 'a.png'"), "job 1: it doesn't have a seed");
+  assert_eq!(
+    job("generator = 'test/2d'\noutput = 'a.png'\nseed = 'a'\nseed-int = 3"),
+    "job 1: it has more than one seed: seed, seed-int",
+  );
+  assert_eq!(
+    job("generator = 'test/nope'\noutput = 'a.png'\nseed = 'a'"),
+    "job 1: there's no generator called test/nope",
+  );
+  assert_eq!(
+    job("generator = 'test/2d'\noutput = 'a.png'\nseed = 'a'\nouptut = 'b.png'"),
+    "job 1: there's no option called ouptut",
+  );
+  assert_eq!(
+    job("generator = 'basic/worley'\noutput = 'a.png'\nseed = 'a'\nparams = { size = [4, 4], octave = 2 }"),
+    "job 1: there's no parameter named octave",
+  );
+  assert_eq!(
+    job("generator = 'test/2d'\noutput = 'a.png'\nseed = 'a'\nregion = [0, 0, 10]"),
+    "job 1: [0, 0, 10] isn't a list of 4 nonnegative integers",
+  );
+  assert_eq!(parse("").err().unwrap(), "there aren't any jobs in it");
+  assert_eq!(parse("[jobs]").err().unwrap(), "there's no section called jobs");
+}
@@ -0,0 +1,55 @@
+//! Load the example plugin, the way hart loads everything in the plugins directory.
+
+use {
+  hart::{gens::plugin::{self, PluginGen}, output::Output, params, Category, Gen, Registry},
+  image::GenericImageView as _,
+  std::{env, path::PathBuf},
+};
+
+/// The example plugin, which cargo builds alongside the tests.
+fn example_plugin() -> PathBuf {
+  let deps = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+  let name = format!("{}gradient_plugin{}", env::consts::DLL_PREFIX, env::consts::DLL_SUFFIX);
+  deps.parent().unwrap().join("examples").join(name)
+}
+
+#[test]
+fn plugins_describe_themselves() {
+  let gen = unsafe { PluginGen::load(&example_plugin()) }.unwrap();
+  assert_eq!(gen.category(), Category::Plugin);
+  assert_eq!(gen.command(), "gradient");
+  let names: Vec<_> = gen.params().iter().map(|param| param.name).collect();
+  assert_eq!(names, ["size", "vertical"]);
+  assert_eq!(gen.params()[0].default, Some("256x256"));
+}
+
+#[test]
+fn plugins_run_like_any_generator() {
+  let gen = unsafe { PluginGen::load(&example_plugin()) }.unwrap();
+  let params = params::resolve(&gen.params(), [("size", "30x20")].iter().cloned()).unwrap();
+  let img = match gen.run(&params, b"seed").unwrap() {
+    Output::Image(img) => img.to_dynamic(),
+    Output::Raw(_) => panic!("the plugin should make an image"),
+  };
+  assert_eq!(img.dimensions(), (30, 20));
+  // a horizontal gradient, so every column is one color
+  let rgb = img.to_rgb8();
+  assert!((0..20).all(|y| rgb.get_pixel(7, y) == rgb.get_pixel(7, 0)));
+  assert_ne!(rgb.get_pixel(0, 0), rgb.get_pixel(29, 0));
+}
+
+#[test]
+fn plugin_dirs_register_everything_loadable() {
+  let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("plugins");
+  std::fs::create_dir_all(&dir).unwrap();
+  let lib = dir.join(example_plugin().file_name().unwrap());
+  std::fs::copy(example_plugin(), &lib).unwrap();
+  let broken = dir.join(format!("broken.{}", env::consts::DLL_EXTENSION));
+  std::fs::write(&broken, b"not a library").unwrap();
+
+  let mut registry = Registry::builtin();
+  let failed = unsafe { plugin::load_dir(&mut registry, &dir) };
+  assert_eq!(failed.iter().map(|(path, _)| path).collect::<Vec<_>>(), [&broken]);
+  assert!(registry.find("plugin", "gradient").is_some());
+  assert!(registry.categories().contains(&Category::Plugin));
+}
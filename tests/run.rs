@@ -0,0 +1,163 @@
+//! Run generators and write their output through `hart::run`, the way the `hart` binary does.
+
+use {
+  hart::{
+    output::{Encoding, Format},
+    params,
+    recipe::Recipe,
+    run::{self, Exit, Failure, Render},
+    GenFail,
+    Registry,
+    VERSION,
+  },
+  std::{fs, io, path::PathBuf},
+};
+
+/// A fresh, empty scratch directory for one test.
+fn scratch(name: &str) -> PathBuf {
+  let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("run").join(name);
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+  dir
+}
+
+/// Everything to render basic/worley at some size, ready for `run::generate`.
+fn worley(seed: &str, size: &str, output_path: String) -> Render {
+  let gen = Registry::builtin().find("basic", "worley").unwrap();
+  let params = params::resolve(&gen.params(), [("size", size)]).unwrap();
+  let recipe = Recipe {
+    version: VERSION.to_string(),
+    generator: "basic/worley".to_string(),
+    params: params.to_text(),
+    seed: seed.as_bytes().to_vec(),
+    view: None,
+  };
+  Render { params, recipe, encoding: Encoding::new(Format::Png), output_path }
+}
+
+/// Everything in a directory, by name.
+fn listing(dir: &PathBuf) -> Vec<String> {
+  let mut names: Vec<_> = fs::read_dir(dir).unwrap()
+    .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+    .collect();
+  names.sort();
+  names
+}
+
+#[test]
+fn reproducing_makes_the_same_image() {
+  let dir = scratch("reproduce");
+  let original = dir.join("original.png").display().to_string();
+  let gen = Registry::builtin().find("basic", "worley").unwrap();
+  let Render { params, recipe, encoding, .. } = worley("again", "24x16", original.clone());
+  run::generate(gen, &params, &recipe, &encoding, None, false, &original).unwrap();
+
+  let copy = run::reproduced_path(&original);
+  assert!(copy.ends_with("original-reproduced.png"));
+  run::reproduce(&Registry::builtin(), &original, &copy, &encoding, None, false).unwrap();
+  assert_eq!(fs::read(&original).unwrap(), fs::read(&copy).unwrap());
+}
+
+#[test]
+fn failures_leave_nothing_behind() {
+  let dir = scratch("failures");
+  let gen = Registry::builtin().find("project", "mottle").unwrap();
+  let missing = dir.join("missing.png").display().to_string();
+  let params = params::resolve(&gen.params(), [("from", missing.as_str()), ("to", missing.as_str())]).unwrap();
+  let recipe = Recipe {
+    version: VERSION.to_string(),
+    generator: "project/mottle".to_string(),
+    params: params.to_text(),
+    seed: b"seed".to_vec(),
+    view: None,
+  };
+  let output_path = dir.join("blend.png").display().to_string();
+  let failure = run::generate(gen, &params, &recipe, &Encoding::default(), None, false, &output_path).unwrap_err();
+  assert_eq!(failure.exit, Exit::NoInput);
+  assert!(run::describe(&failure.error).starts_with(&format!("couldn't open {}: ", missing)));
+  assert!(listing(&dir).is_empty());
+}
+
+#[test]
+fn batches_render_every_variant_to_its_own_output() {
+  let dir = scratch("batch");
+  let gen = Registry::builtin().find("basic", "worley").unwrap();
+  let renders = vec![
+    worley("a", "8x8", dir.join("a.png").display().to_string()),
+    worley("b", "8x8", dir.join("b.png").display().to_string()),
+  ];
+  run::batch(gen, renders, None, false).unwrap();
+  assert_eq!(listing(&dir), ["a.png", "b.png"]);
+
+  let clash = vec![
+    worley("a", "8x8", dir.join("same.png").display().to_string()),
+    worley("b", "8x8", dir.join("same.png").display().to_string()),
+  ];
+  assert_eq!(run::batch(gen, clash, None, false).unwrap_err().exit, Exit::Usage);
+  assert_eq!(listing(&dir), ["a.png", "b.png"]);
+}
+
+#[test]
+fn job_files_run_jobs_after_the_ones_they_need() {
+  let dir = scratch("jobs");
+  let path = dir.join("session.toml");
+  fs::write(&path, r#"
+    [[job]]
+    generator = "project/mottle"
+    seed = "a"
+    output = "out/blend.png"
+    params = { from = "out/cells.png", to = "out/cells.png" }
+
+    [[job]]
+    generator = "basic/worley"
+    seed = "b"
+    output = "out/cells.png"
+    params = { size = [12, 12] }
+  "#).unwrap();
+  let path = path.display().to_string();
+  run::job_file(&Registry::builtin(), &path, None).unwrap();
+  assert_eq!(listing(&dir.join("out")), ["blend.png", "cells.png"]);
+  // what the second job writes isn't an input worth watching, since running the jobs changes it every time
+  assert_eq!(run::job_file_inputs(&Registry::builtin(), &path), [PathBuf::from(&path)]);
+}
+
+#[test]
+fn images_need_a_format_we_know() {
+  let dir = scratch("formats");
+  let gen = Registry::builtin().find("basic", "worley").unwrap();
+  // what the CLI and job files do when nothing says what format to use and the extension is one we don't know
+  let guessed = Encoding { guessed: true, ..Encoding::default() };
+  for name in ["out.webp", "out.jpgg"] {
+    let output_path = dir.join(name).display().to_string();
+    let Render { params, recipe, .. } = worley("a", "8x8", output_path.clone());
+    let failure = run::generate(gen, &params, &recipe, &guessed, None, false, &output_path).unwrap_err();
+    assert_eq!(failure.exit, Exit::Usage);
+  }
+  assert!(listing(&dir).is_empty());
+
+  // raw output doesn't have a format, so any extension will do
+  let ascii = Registry::builtin().find("test", "ascii").unwrap();
+  let params = params::resolve(&ascii.params(), Vec::<(&str, &str)>::new()).unwrap();
+  let recipe = Recipe {
+    version: VERSION.to_string(),
+    generator: "test/ascii".to_string(),
+    params: params.to_text(),
+    seed: b"a".to_vec(),
+    view: None,
+  };
+  let output_path = dir.join("out.txt").display().to_string();
+  run::generate(ascii, &params, &recipe, &guessed, None, false, &output_path).unwrap();
+  assert_eq!(listing(&dir), ["out.txt"]);
+}
+
+#[test]
+fn exit_codes_come_from_the_failure_underneath() {
+  let exit = |error| Failure::from(error).exit;
+  let within = |error| GenFail::Context("couldn't render".to_string(), Box::new(error));
+  assert_eq!(exit(GenFail::BadArg("no".to_string())), Exit::Usage);
+  assert_eq!(exit(within(GenFail::BadArg("no".to_string()))), Exit::Usage);
+  assert_eq!(exit(within(GenFail::Data("mismatched".to_string()))), Exit::Data);
+  assert_eq!(exit(within(GenFail::Plugin("broken".to_string()))), Exit::Software);
+  assert_eq!(exit(within(GenFail::Io(io::ErrorKind::NotFound.into()))), Exit::NoInput);
+  assert_eq!(exit(within(GenFail::Io(io::ErrorKind::BrokenPipe.into()))), Exit::Io);
+}